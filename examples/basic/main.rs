@@ -7,7 +7,8 @@
 //! 
 use amethyst_lyon::{
     RenderLyon,
-    utils::{Mesh, VertexType, ActiveMesh}
+    utils::{Mesh, VertexType, IndexType, ActiveMesh, AA_STROKE},
+    path::{stroke_aa, LyonPath, FillStyle},
 };
 
 use amethyst::{
@@ -61,16 +62,15 @@ impl SimpleState for BasicUsageState {
             -150., -50., 1., 400., 150.,
         );
     
-        let mut text = UiText::new(
+        let text = UiText::new(
             font.clone(),
             "Press 1 to display red shapes\nPress 2 to display green shapes\nPress 0 to display all shapes".to_string(),
             [0., 0., 0., 1.],
             25.,
+            LineMode::Wrap,
+            Anchor::TopLeft,
         );
 
-        text.line_mode = LineMode::Wrap;
-        text.align = Anchor::TopLeft;
-
         world
             .create_entity()
             .with(text_transform)
@@ -90,7 +90,7 @@ impl SimpleState for BasicUsageState {
         builder.line_to(point(400. , 200.));
 
         let path = builder.build();
-        let mut geometry: VertexBuffers<VertexType, u16> = VertexBuffers::new();
+        let mut geometry: VertexBuffers<VertexType, IndexType> = VertexBuffers::new();
         let mut tessellator_stroke = StrokeTessellator::new();
         {
             let stroke_options = StrokeOptions::tolerance(0.02)
@@ -101,10 +101,11 @@ impl SimpleState for BasicUsageState {
             tessellator_stroke.tessellate_path(
                 &path,
                 &stroke_options,
-                &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+                &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                     VertexType {
                         position: pos.to_array(),
                         colour: [1., 0., 0., 1.],
+                        aa: stroke_aa(&attributes, AA_STROKE),
                     }
                 }),
             ).unwrap();
@@ -132,10 +133,11 @@ impl SimpleState for BasicUsageState {
             tessellator_stroke.tessellate_path(
                 &path,
                 &stroke_options,
-                &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+                &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                     VertexType {
                         position: pos.to_array(),
                         colour: [1., 0., 0., 1.],
+                        aa: stroke_aa(&attributes, AA_STROKE),
                     }
                 }),
             ).unwrap();
@@ -163,29 +165,12 @@ impl SimpleState for BasicUsageState {
      
         let path = builder.build();
 
-        let mut geometry: VertexBuffers<VertexType, u16> = VertexBuffers::new();
-        let mut tessellator_fill = FillTessellator::new();
-        {
-             tessellator_fill.tessellate_path(
-                &path,
-                &FillOptions::default(),
-                &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: FillAttributes| {
-                    VertexType {
-                        position: pos.to_array(),
-                        colour: [0., 1., 0., 1.],
-                    }
-                }),
-            ).unwrap();
-        }
+        // rather than tessellating by hand, add the path and let RenderLyon tessellate it
+        let path = LyonPath::new(path).with_fill(FillStyle::new([0., 1., 0., 1.]));
 
-        let mesh = Mesh {
-            vertices: geometry.vertices,
-            indices: geometry.indices,
-            ..Mesh::default()
-        };
         self.mesh2 = Some(world
             .create_entity()
-            .with(mesh)
+            .with(path)
             .build());
     }
 
@@ -198,7 +183,7 @@ impl SimpleState for BasicUsageState {
 
         match &event {
             StateEvent::Window(event) => {
-                if is_close_requested(&event) || is_key_down(&event, VirtualKeyCode::Escape) {
+                if is_close_requested(event) || is_key_down(event, VirtualKeyCode::Escape) {
                     Trans::Quit
                 } else {
                     Trans::None
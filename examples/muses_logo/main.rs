@@ -5,14 +5,18 @@
 //! Copyright © 2020 Benedict Gaster. All rights reserved.
 //! 
 //! 
+// the logo's coordinates are copied from its SVG, to 4 decimal places
+#![allow(clippy::excessive_precision)]
+
 use amethyst_lyon::{
     RenderLyon,
-    utils::{Mesh, VertexType, ActiveMesh}
+    utils::{Mesh, VertexType, IndexType, AA_STROKE},
+    path::stroke_aa,
 };
 
 use amethyst::{
     input::{
-        is_close_requested, is_key_down, InputBundle, InputEvent, StringBindings, Button,
+        is_close_requested, is_key_down, InputBundle, StringBindings,
     },
     core::{
         transform::TransformBundle, 
@@ -26,9 +30,7 @@ use amethyst::{
     },
     winit::VirtualKeyCode,
     ecs::prelude::{Entity, WorldExt},
-    assets::{Loader},
-    ui::{RenderUi, UiBundle, UiText, LineMode},
-    ui::{Anchor, TtfFormat, UiTransform},
+    ui::{RenderUi, UiBundle},
     utils::{application_root_dir},
 };
 
@@ -53,7 +55,7 @@ impl SimpleState for BasicUsageState {
         .with_line_join(LineJoin::Round)
         .with_line_cap(LineCap::Round);
 
-        let mut geometry: VertexBuffers<VertexType, u16> = VertexBuffers::new();
+        let mut geometry: VertexBuffers<VertexType, IndexType> = VertexBuffers::new();
         let mut tessellator_stroke = StrokeTessellator::new();
 
         let red = [0.7231, 0.0685, 0.0160, 1.0];
@@ -69,10 +71,11 @@ impl SimpleState for BasicUsageState {
         tessellator_stroke.tessellate_path(
             &path,
             &stroke_options,
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+            &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                 VertexType {
                     position: pos.to_array(),
                     colour: red,
+                    aa: stroke_aa(&attributes, AA_STROKE),
                 }
             }),
         ).unwrap();
@@ -83,10 +86,11 @@ impl SimpleState for BasicUsageState {
         tessellator_stroke.tessellate_path(
             &path,
             &stroke_options,
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+            &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                 VertexType {
                     position: pos.to_array(),
                     colour: yellow,
+                    aa: stroke_aa(&attributes, AA_STROKE),
                 }
             }),
         ).unwrap();
@@ -99,10 +103,11 @@ impl SimpleState for BasicUsageState {
         tessellator_stroke.tessellate_path(
             &path,
             &stroke_options,
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+            &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                 VertexType {
                     position: pos.to_array(),
                     colour: red,
+                    aa: stroke_aa(&attributes, AA_STROKE),
                 }
             }),
         ).unwrap();
@@ -113,10 +118,11 @@ impl SimpleState for BasicUsageState {
         tessellator_stroke.tessellate_path(
             &path,
             &stroke_options,
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+            &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                 VertexType {
                     position: pos.to_array(),
                     colour: blue,
+                    aa: stroke_aa(&attributes, AA_STROKE),
                 }
             }),
         ).unwrap();
@@ -133,10 +139,11 @@ impl SimpleState for BasicUsageState {
         tessellator_stroke.tessellate_path(
             &path,
             &stroke_options,
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+            &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                 VertexType {
                     position: pos.to_array(),
                     colour: blue,
+                    aa: stroke_aa(&attributes, AA_STROKE),
                 }
             }),
         ).unwrap();
@@ -147,10 +154,11 @@ impl SimpleState for BasicUsageState {
         tessellator_stroke.tessellate_path(
             &path,
             &stroke_options,
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| {
+            &mut BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
                 VertexType {
                     position: pos.to_array(),
                     colour: red,
+                    aa: stroke_aa(&attributes, AA_STROKE),
                 }
             }),
         ).unwrap();
@@ -169,22 +177,17 @@ impl SimpleState for BasicUsageState {
 
     fn handle_event(
         &mut self,
-        data: StateData<'_, GameData<'_, '_>>,
+        _data: StateData<'_, GameData<'_, '_>>,
         event: StateEvent,
     ) -> SimpleTrans {
-        let StateData { world, .. } = data;
-
         match &event {
             StateEvent::Window(event) => {
-                if is_close_requested(&event) || is_key_down(&event, VirtualKeyCode::Escape) {
+                if is_close_requested(event) || is_key_down(event, VirtualKeyCode::Escape) {
                     Trans::Quit
                 } else {
                     Trans::None
                 }
             }
-            StateEvent::Input(input) => {      
-                Trans::None
-            }
             _ => Trans::None,
         }
     }
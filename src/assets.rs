@@ -0,0 +1,321 @@
+//! Description:
+//!
+//! Mesh asset, so that Lyon geometry can be loaded from file with the
+//! amethyst Loader, e.g. with svg::SvgFormat.
+//!
+//! Entities are given a Handle<LyonMesh>, and once the asset has loaded
+//! the Lyon pass draws it, uploading the asset's geometry once however many
+//! entities reference it. Such entities need no Mesh of their own, and are
+//! drawn with the asset's scale and gradient, if any.
+//!
+//! Tessellating complex art can be slow, so meshes can also be written once,
+//! with LyonMesh::to_bytes, into a compact versioned binary format, and then
+//! loaded quickly with MeshBinFormat.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use amethyst::{
+    assets::{Asset, Format, Handle},
+    core::{ecs::VecStorage, math::Vector2},
+};
+use amethyst_error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use std::convert::TryFrom;
+
+use crate::gradient::Gradient;
+use crate::utils::{vertex_args, vertex_bounds, CustomArgs, IndexType, Mesh, VertexType};
+
+/// Mesh asset, the geometry of a mesh loaded through the Loader
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LyonMesh {
+    /// list of vertices contained within mesh
+    pub vertices: Vec<VertexType>,
+    /// indices for vertices of each triangle in mesh
+    pub indices: Vec<IndexType>,
+    /// scaling factor to be applied to all vertices
+    #[serde(default = "default_scale")]
+    pub scale: Vector2<f32>,
+    /// gradient painting the mesh, if any, multiplied with the colour of each vertex, 
+    /// except those marked AA_UNPAINTED
+    #[serde(default)]
+    pub gradient: Option<Gradient>,
+}
+
+fn default_scale() -> Vector2<f32> {
+    Vector2::new(1.0, 1.0)
+}
+
+impl Default for LyonMesh {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            scale: default_scale(),
+            gradient: None,
+        }
+    }
+}
+
+impl Asset for LyonMesh {
+    const NAME: &'static str = "amethyst_lyon::LyonMesh";
+    type Data = Self;
+    type HandleStorage = VecStorage<Handle<Self>>;
+}
+
+impl From<&Mesh> for LyonMesh {
+    fn from(mesh: &Mesh) -> Self {
+        LyonMesh {
+            vertices: mesh.vertices.clone(),
+            indices: mesh.indices.clone(),
+            scale: mesh.scale,
+            gradient: mesh.gradient.clone(),
+        }
+    }
+}
+
+impl From<&LyonMesh> for Mesh {
+    fn from(asset: &LyonMesh) -> Self {
+        Mesh {
+            vertices: asset.vertices.clone(),
+            indices: asset.indices.clone(),
+            scale: asset.scale,
+            gradient: asset.gradient.clone(),
+        }
+    }
+}
+
+/// Magic number at the start of every binary mesh file
+pub const MESH_BIN_MAGIC: [u8; 4] = *b"LYON";
+
+/// Version of the binary mesh format written by LyonMesh::to_bytes.
+/// Version 2 added the anti-aliasing coordinate of each vertex, and version 3 the
+/// mesh's scale and gradient.
+pub const MESH_BIN_VERSION: u32 = 3;
+
+const HEADER_LEN: usize = 8;
+
+/// Indices of a binary mesh, stored at the width of the crate that wrote them,
+/// so that files stay compact and can be read whether or not `u32-indices` is enabled
+#[derive(Deserialize, Serialize)]
+enum BinIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+/// Body of a binary mesh, following the magic number and version
+#[derive(Deserialize, Serialize)]
+struct BinMesh {
+    vertices: Vec<VertexType>,
+    indices: BinIndices,
+    scale: [f32; 2],
+    gradient: Option<Gradient>,
+}
+
+impl LyonMesh {
+    /// Returns an vector of vertices expected by GLSL vert shader
+    pub fn get_args(&self) -> Vec<CustomArgs> {
+        vertex_args(&self.vertices)
+    }
+
+    /// Returns the bottom-left and top-right corners of the mesh's bounds, or None 
+    /// if the mesh has no vertices
+    pub fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        vertex_bounds(&self.vertices)
+    }
+
+    /// Serialize the mesh into the binary mesh format, loaded with MeshBinFormat.
+    /// The format is the magic number, the little endian format version, and then
+    /// the bincode encoded vertices, indices, scale and gradient.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        #[cfg(not(feature = "u32-indices"))]
+        let indices = BinIndices::U16(self.indices.clone());
+        #[cfg(feature = "u32-indices")]
+        let indices = BinIndices::U32(self.indices.clone());
+
+        let body = BinMesh {
+            vertices: self.vertices.clone(),
+            indices,
+            scale: [self.scale.x, self.scale.y],
+            gradient: self.gradient.clone(),
+        };
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&MESH_BIN_MAGIC);
+        bytes.extend_from_slice(&MESH_BIN_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, &body)
+            .map_err(|e| Error::from_string(format!("Failed to serialize mesh: {}", e)))?;
+        Ok(bytes)
+    }
+
+    /// Deserialize a mesh from the binary mesh format, written by to_bytes. Files whose
+    /// indices are out of range of their vertices are rejected, as they would be drawn
+    /// with reads past the end of the vertex buffer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN || bytes[..4] != MESH_BIN_MAGIC {
+            return Err(Error::from_string("Not a binary Lyon mesh, magic number not found"));
+        }
+
+        let mut version = [0; 4];
+        version.copy_from_slice(&bytes[4..HEADER_LEN]);
+        let version = u32::from_le_bytes(version);
+        if version != MESH_BIN_VERSION {
+            return Err(Error::from_string(format!(
+                "Unsupported binary Lyon mesh version {}, expected {}",
+                version, MESH_BIN_VERSION
+            )));
+        }
+
+        let body: BinMesh = bincode::deserialize(&bytes[HEADER_LEN..])
+            .map_err(|e| Error::from_string(format!("Failed to deserialize mesh: {}", e)))?;
+
+        let indices = match body.indices {
+            // a no-op conversion unless the u32-indices feature is enabled
+            #[allow(clippy::useless_conversion)]
+            BinIndices::U16(indices) => indices.into_iter().map(IndexType::from).collect(),
+            BinIndices::U32(indices) => indices
+                .into_iter()
+                .map(IndexType::try_from)
+                .collect::<Result<Vec<IndexType>, _>>()
+                .map_err(|_| {
+                    Error::from_string("Binary Lyon mesh has 32 bit indices, enable the u32-indices feature to load it")
+                })?,
+        };
+
+        let vertex_count = body.vertices.len();
+        if let Some(index) = indices.iter().find(|&&index| index as usize >= vertex_count) {
+            return Err(Error::from_string(format!(
+                "Binary Lyon mesh has index {} out of range of its {} vertices",
+                index, vertex_count
+            )));
+        }
+
+        Ok(LyonMesh {
+            vertices: body.vertices,
+            indices,
+            scale: Vector2::new(body.scale[0], body.scale[1]),
+            gradient: body.gradient,
+        })
+    }
+}
+
+/// Format for binary mesh files, written with LyonMesh::to_bytes, loaded as a LyonMesh:
+///
+/// ```ignore
+/// // write once, e.g. in a build step
+/// std::fs::write("logo.lmesh", LyonMesh::from(&mesh).to_bytes()?)?;
+///
+/// // and load quickly at startup
+/// let handle = loader.load("logo.lmesh", MeshBinFormat, (), &world.read_resource());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshBinFormat;
+
+impl Format<LyonMesh> for MeshBinFormat {
+    fn name(&self) -> &'static str {
+        "LyonMeshBin"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<LyonMesh, Error> {
+        LyonMesh::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lyon::math::point;
+
+    fn triangle() -> LyonMesh {
+        let vertex = |x, y| VertexType {
+            position: [x, y],
+            colour: [1.0, 0.5, 0.25, 1.0],
+            aa: [-1.0, 1.0],
+        };
+        LyonMesh {
+            vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+            indices: vec![0, 1, 2],
+            scale: Vector2::new(2.0, 3.0),
+            gradient: Some(Gradient::linear(point(0.0, 0.0), point(1.0, 0.0)).with_stop(0.5, [0.0, 1.0, 0.0, 1.0])),
+        }
+    }
+
+    /// Bytes of a mesh with body, as to_bytes would write it
+    fn with_header(version: u32, body: &BinMesh) -> Vec<u8> {
+        let mut bytes = MESH_BIN_MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend(bincode::serialize(body).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let mesh = triangle();
+        assert_eq!(LyonMesh::from_bytes(&mesh.to_bytes().unwrap()).unwrap(), mesh);
+    }
+
+    #[test]
+    fn round_trip_through_mesh() {
+        let mesh = triangle();
+        assert_eq!(LyonMesh::from(&Mesh::from(&mesh)), mesh);
+    }
+
+    #[test]
+    fn rejects_wrong_magic() {
+        let mut bytes = triangle().to_bytes().unwrap();
+        bytes[0] = b'X';
+        assert!(LyonMesh::from_bytes(&bytes).is_err());
+        assert!(LyonMesh::from_bytes(b"LYO").is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_version() {
+        let mut bytes = triangle().to_bytes().unwrap();
+        bytes[4..HEADER_LEN].copy_from_slice(&(MESH_BIN_VERSION - 1).to_le_bytes());
+        assert!(LyonMesh::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        let bytes = triangle().to_bytes().unwrap();
+        assert!(LyonMesh::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_index() {
+        let mesh = triangle();
+        let body = BinMesh {
+            vertices: mesh.vertices,
+            indices: BinIndices::U16(vec![0, 1, 3]),
+            scale: [1.0, 1.0],
+            gradient: None,
+        };
+        assert!(LyonMesh::from_bytes(&with_header(MESH_BIN_VERSION, &body)).is_err());
+    }
+
+    #[test]
+    fn narrows_u32_indices() {
+        let mesh = triangle();
+        let body = BinMesh {
+            vertices: mesh.vertices.clone(),
+            indices: BinIndices::U32(vec![0, 1, 2]),
+            scale: [2.0, 3.0],
+            gradient: mesh.gradient.clone(),
+        };
+        assert_eq!(LyonMesh::from_bytes(&with_header(MESH_BIN_VERSION, &body)).unwrap(), mesh);
+    }
+
+    #[cfg(not(feature = "u32-indices"))]
+    #[test]
+    fn rejects_u32_indices_too_wide_for_u16() {
+        let body = BinMesh {
+            vertices: vec![VertexType::default(); 3],
+            indices: BinIndices::U32(vec![0, 1, 70_000]),
+            scale: [1.0, 1.0],
+            gradient: None,
+        };
+        assert!(LyonMesh::from_bytes(&with_header(MESH_BIN_VERSION, &body)).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Description:
+//!
+//! Linear and radial gradient paints, evaluated per pixel in the fragment
+//! shader, so that smooth gradients do not need dense tessellation.
+//!
+//! A gradient paints a whole mesh, in mesh coordinates (i.e. before the mesh's
+//! scale and transform), and the colour of each vertex is multiplied by it,
+//! except for vertices marked utils::AA_UNPAINTED, such as those of a stroke
+//! tessellated along with a gradient fill.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use lyon::math::Point;
+
+use serde::{Deserialize, Serialize};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Maximum number of stops in a gradient, further stops are ignored
+pub const MAX_GRADIENT_STOPS: usize = 4;
+
+/// If a gradient with too many stops has been warned about, as gradients are drawn every frame
+static WARNED_STOPS: AtomicBool = AtomicBool::new(false);
+
+/// Colour stop of a gradient, at offset between 0 (start) and 1 (end)
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub colour: [f32; 4],
+}
+
+/// Geometry of a gradient, with points in mesh coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum GradientKind {
+    /// Gradient along the line from start to end
+    Linear { start: [f32; 2], end: [f32; 2] },
+    /// Gradient out from center to a circle of radius
+    Radial { center: [f32; 2], radius: f32 },
+}
+
+/// Gradient paint, with up to MAX_GRADIENT_STOPS colour stops, those with the lowest offsets 
+/// are used if there are more. Before the first stop, and after the last, the colour of the 
+/// nearest stop is used.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    pub stops: Vec<GradientStop>,
+}
+
+impl Gradient {
+    /// Create a linear gradient from start to end, with no stops
+    pub fn linear(start: Point, end: Point) -> Self {
+        Self {
+            kind: GradientKind::Linear {
+                start: start.to_array(),
+                end: end.to_array(),
+            },
+            stops: Vec::new(),
+        }
+    }
+
+    /// Create a radial gradient from center out to radius, with no stops
+    pub fn radial(center: Point, radius: f32) -> Self {
+        Self {
+            kind: GradientKind::Radial {
+                center: center.to_array(),
+                radius,
+            },
+            stops: Vec::new(),
+        }
+    }
+
+    /// Add a stop of colour at offset, stops are kept in offset order
+    pub fn with_stop(mut self, offset: f32, colour: [f32; 4]) -> Self {
+        let at = self.stops.iter().take_while(|stop| stop.offset <= offset).count();
+        self.stops.insert(at, GradientStop { offset, colour });
+        self
+    }
+
+    /// Parameters of the gradient, as expected by the shaders:
+    /// (kind and stop count, geometry, stop offsets, stop colours)
+    pub(crate) fn shader_args(&self) -> ([f32; 4], [f32; 4], [f32; 4], [[f32; 4]; 4]) {
+        let (kind, geometry) = match self.kind {
+            GradientKind::Linear { start, end } => (1.0, [start[0], start[1], end[0], end[1]]),
+            GradientKind::Radial { center, radius } => (2.0, [center[0], center[1], radius, 0.0]),
+        };
+
+        // stops can be set directly, e.g. from a prefab, so are not necessarily in order
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap_or(std::cmp::Ordering::Equal));
+        if stops.len() > MAX_GRADIENT_STOPS && !WARNED_STOPS.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Gradient has {} stops, only the first {} are drawn",
+                stops.len(),
+                MAX_GRADIENT_STOPS
+            );
+        }
+        stops.truncate(MAX_GRADIENT_STOPS);
+
+        let mut offsets = [0.0; 4];
+        let mut colours = [[1.0; 4]; 4];
+        for (i, stop) in stops.iter().enumerate() {
+            offsets[i] = stop.offset;
+            colours[i] = stop.colour;
+        }
+
+        ([kind, stops.len() as f32, 0.0, 0.0], geometry, offsets, colours)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lyon::math::point;
+
+    #[test]
+    fn sorts_stops_before_dropping_extra_ones() {
+        let stop = |offset, red| GradientStop { offset, colour: [red, 0.0, 0.0, 1.0] };
+        let gradient = Gradient {
+            kind: GradientKind::Radial { center: [0.0, 0.0], radius: 1.0 },
+            stops: vec![stop(1.0, 0.5), stop(0.8, 0.4), stop(0.6, 0.3), stop(0.0, 0.0), stop(0.2, 0.1), stop(0.4, 0.2)],
+        };
+        let ([kind, count, _, _], _, offsets, colours) = gradient.shader_args();
+        assert_eq!(kind, 2.0);
+        assert_eq!(count, MAX_GRADIENT_STOPS as f32);
+        assert_eq!(offsets, [0.0, 0.2, 0.4, 0.6]);
+        assert_eq!(colours.iter().map(|colour| colour[0]).collect::<Vec<_>>(), vec![0.0, 0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn with_stop_keeps_offset_order() {
+        let gradient = Gradient::linear(point(0.0, 0.0), point(1.0, 0.0))
+            .with_stop(1.0, [1.0; 4])
+            .with_stop(0.0, [0.0; 4])
+            .with_stop(0.5, [0.5; 4]);
+        let offsets: Vec<f32> = gradient.stops.iter().map(|stop| stop.offset).collect();
+        assert_eq!(offsets, vec![0.0, 0.5, 1.0]);
+    }
+}
@@ -4,16 +4,28 @@
 //! 
 //! Copyright © 2020 Benedict Gaster. All rights reserved.
 //! 
+pub mod assets;
+pub mod gradient;
 pub mod pass;
+pub mod path;
+pub mod prefab;
+pub mod shapes;
+pub mod svg;
+pub mod texture;
 pub mod utils;
+pub mod window;
 
 use amethyst::{
+    assets::{Handle, Processor},
     core::{
         ecs::{
             DispatcherBuilder, World,
         },
+        transform::Transform,
+        Hidden, HiddenPropagate,
     },
     prelude::*,
+    ui::UiTransform,
     renderer::{
         bundle::{RenderOrder, RenderPlan, RenderPlugin, Target},
         rendy::{
@@ -22,29 +34,175 @@ use amethyst::{
                 render::{RenderGroupDesc},
             },
         },
+        resources::Tint,
         types::Backend,
+        Camera,
     },
 };
 
 use amethyst_error::Error;
 
-use crate::utils::{Mesh, ActiveMesh};
+use std::ops::{Bound, RangeBounds};
+
+use crate::utils::{
+    Mesh, ActiveMesh, MeshFilter, MeshTag, WorldSpace, LyonLayer, ClipMask, ScissorRect, BlendMode, InstanceOf,
+    CoordinateMode, CoordinateSystem,
+};
 use crate::pass::{DrawLyonDesc};
+use crate::path::TessellatePathSystem;
+use crate::assets::LyonMesh;
+use crate::texture::TextureFill;
+
+/// Render plugin for Lyon meshes. By default meshes are drawn to the main target, 
+/// in the transparent render order, alpha blended and anti-aliased.
+///
+/// Several instances can be added to the rendering bundle, e.g. a background drawn 
+/// before sprites and an overlay drawn after the UI, each drawing the meshes on its 
+/// own range of LyonLayers:
+///
+/// ```ignore
+/// RenderingBundle::<DefaultBackend>::new()
+///     .with_plugin(RenderLyon::default().with_order(RenderOrder::BeforeTransparent).with_layers(..0))
+///     .with_plugin(RenderFlat2D::default())
+///     .with_plugin(RenderUi::default())
+///     .with_plugin(RenderLyon::default().with_order(RenderOrder::Overlay).with_layers(0..))
+/// ```
+#[derive(Debug)]
+pub struct RenderLyon {
+    target: Target,
+    /// RenderOrder, or a custom order between them
+    order: i32,
+    stencil: bool,
+    blend: BlendMode,
+    anti_alias: bool,
+    mode: CoordinateMode,
+    coordinates: Option<CoordinateSystem>,
+    layers: Option<(Bound<i32>, Bound<i32>)>,
+}
+
+impl Default for RenderLyon {
+    fn default() -> Self {
+        Self {
+            target: Target::Main,
+            order: RenderOrder::Transparent.into(),
+            stencil: false,
+            blend: BlendMode::Alpha,
+            anti_alias: true,
+            mode: CoordinateMode::Screen,
+            coordinates: None,
+            layers: None,
+        }
+    }
+}
+
+impl RenderLyon {
+    /// Set the render target meshes are drawn to
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Set when meshes are drawn, relative to the other render plugins of the target, 
+    /// as a RenderOrder or a custom i32 between them. Plugins with the same order are 
+    /// drawn in the order they were added.
+    pub fn with_order<O: Into<i32>>(mut self, order: O) -> Self {
+        self.order = order.into();
+        self
+    }
+
+    /// Enable utils::ClipMask clipping, which uses the stencil buffer. The target 
+    /// must then have a depth-stencil attachment, e.g. of format D24UnormS8Uint, that 
+    /// is cleared to 0 each frame, as window::RenderToWindowWithStencil creates.
+    pub fn with_stencil(mut self, stencil: bool) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
+    /// Set the blend mode of meshes without a utils::BlendMode component
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Set whether anti-aliased strokes and fill fringes are feathered. Disabling it 
+    /// draws strokes aliased and drops fringes, e.g. for a multisampled target, 
+    /// without re-tessellating.
+    pub fn with_anti_alias(mut self, anti_alias: bool) -> Self {
+        self.anti_alias = anti_alias;
+        self
+    }
+
+    /// Set the space meshes without a utils::WorldSpace marker are drawn in
+    pub fn with_coordinate_mode(mut self, mode: CoordinateMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the coordinate system of screen space, i.e. its origin and units. This sets 
+    /// the utils::CoordinateSystem resource, so applies to every RenderLyon plugin.
+    pub fn with_coordinate_system(mut self, coordinates: CoordinateSystem) -> Self {
+        self.coordinates = Some(coordinates);
+        self
+    }
+
+    /// Draw only meshes whose LyonLayer is in layers, meshes without one are on layer 0
+    pub fn with_layers<R: RangeBounds<i32>>(mut self, layers: R) -> Self {
+        self.layers = Some((layers.start_bound().cloned(), layers.end_bound().cloned()));
+        self
+    }
+}
 
-#[derive(Default, Debug)]
-pub struct RenderLyon {}
+/// Marker resource, inserted once the systems shared by all RenderLyon plugins have been added
+#[derive(Debug, Default)]
+struct LyonSystems;
 
 /// Render plugin for Lyon tessellator
 impl<B: Backend> RenderPlugin<B> for RenderLyon {
     fn on_build<'a, 'b>(
         &mut self,
         world: &mut World,
-        _builder: &mut DispatcherBuilder<'a, 'b>,
+        builder: &mut DispatcherBuilder<'a, 'b>,
     ) -> Result<(), Error> {
-        // Add the required components to the world ECS
+        // Add the required components to the world ECS, registering them again for
+        // each plugin is harmless
         world.register::<Mesh>();
+        world.register::<Handle<LyonMesh>>();
+        world.register::<Transform>();
+        world.register::<UiTransform>();
+        world.register::<WorldSpace>();
+        world.register::<LyonLayer>();
+        world.register::<Tint>();
+        world.register::<TextureFill>();
+        world.register::<InstanceOf>();
+        world.register::<ClipMask>();
+        world.register::<ScissorRect>();
+        world.register::<BlendMode>();
+        world.register::<Hidden>();
+        world.register::<HiddenPropagate>();
+        world.register::<Camera>();
         world.register::<ActiveMesh>();
-        world.insert(ActiveMesh::default());
+        world.register::<MeshTag>();
+        // resources are shared, so must not be reset by another plugin
+        world.entry::<ActiveMesh>().or_insert_with(ActiveMesh::default);
+        world.entry::<MeshFilter>().or_insert_with(MeshFilter::default);
+        match self.coordinates {
+            Some(coordinates) => world.insert(coordinates),
+            None => {
+                world.entry::<CoordinateSystem>().or_insert_with(CoordinateSystem::default);
+            }
+        }
+
+        // the systems are shared too, and are only added by the first plugin
+        if world.has_value::<LyonSystems>() {
+            return Ok(());
+        }
+        world.insert(LyonSystems);
+
+        // Tessellate paths into meshes, before they are rendered
+        builder.add(TessellatePathSystem::default(), "lyon_tessellate_path_system", &[]);
+
+        // Load mesh assets, which are drawn for each entity with a handle to them
+        builder.add(Processor::<LyonMesh>::new(), "lyon_mesh_processor", &[]);
         Ok(())
     }
 
@@ -54,11 +212,18 @@ impl<B: Backend> RenderPlugin<B> for RenderLyon {
         _factory: &mut Factory<B>,
         _world: &World,
     ) -> Result<(), Error> {
-        plan.extend_target(Target::Main, |ctx| {
+        let desc = DrawLyonDesc::new()
+            .with_stencil(self.stencil)
+            .with_blend(self.blend)
+            .with_anti_alias(self.anti_alias)
+            .with_coordinate_mode(self.mode)
+            .with_layers(self.layers);
+        let order = self.order;
+        plan.extend_target(self.target, move |ctx| {
             // Add our Description
-            ctx.add(RenderOrder::Transparent, DrawLyonDesc::new().builder())?;
+            ctx.add(order, desc.builder())?;
             Ok(())
         });
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -4,16 +4,71 @@
 //! 
 //! It is all fairly simple. It provides support for rendering multiple 
 //! util::Mesh(es), or if utils:ActiveMesh(entity) exits it just renderers 
-//! the single mesh.
+//! the single mesh. The utils::MeshFilter resource further restricts which
+//! meshes are rendered. If a mesh's entity has a Transform, then its global 
+//! matrix is applied when drawing, so meshes can be moved, rotated and 
+//! scaled (including via a Parent hierarchy) without re-tessellating.
+//! 
+//! Meshes are drawn in screen space by default, with the origin and units of 
+//! the utils::CoordinateSystem resource, while meshes marked with 
+//! utils::WorldSpace are drawn through the active camera, alongside sprites.
+//! In CoordinateMode::Ui, meshes of entities with a UiTransform are placed by 
+//! it instead, centred on the UI element and scaled with its size, and on each 
+//! layer are drawn in the order of their global z, as RenderUi does.
+//! Meshes are drawn in painter's order, sorted by their utils::LyonLayer. Meshes 
+//! on the same layer are grouped by blend mode, and within a blend mode are drawn 
+//! in entity order, so entity order only holds between meshes with the same blend 
+//! mode. Meshes with the Hidden
+//! or HiddenPropagate components are not drawn. The colour of a mesh's vertices 
+//! is multiplied by its entity's Tint, if it has one, so meshes can be faded 
+//! or flashed without touching their vertices.
+//! 
+//! Each mesh's transform, tint and gradient paint are passed to the shaders in 
+//! an instance rate vertex buffer, one instance per draw, and gradients are 
+//! evaluated per pixel in the fragment shader. Meshes with a texture::TextureFill 
+//! are drawn with a second pipeline, which samples the texture, and are skipped 
+//! until their texture has loaded.
+//! 
+//! Entities with a Handle<assets::LyonMesh>, and no Mesh, are drawn with the 
+//! asset once it has loaded. Each asset's geometry is uploaded once, however 
+//! many entities reference it, and again if the asset is reloaded or its handle's 
+//! id is reused by another asset.
+//! 
+//! Entities with a utils::InstanceOf are drawn with the mesh of another entity, 
+//! whose geometry is uploaded once however many instances there are. Consecutive 
+//! draws of the same geometry, with the same state, are batched into a single 
+//! instanced draw call.
+//! 
+//! Meshes with a utils::ClipMask are clipped to the mesh of the mask entity. When 
+//! the mask changes between draws, the previous mask mesh is drawn into the stencil 
+//! buffer again to clear it to 0, the new mask mesh is drawn into it as 1, and the 
+//! clipped meshes are drawn with a stencil test against 1. The stencil reference is 
+//! static pipeline state, as it can't be set per draw. This needs a target with a 
+//! stencil buffer, e.g. from window::RenderToWindowWithStencil, and is only enabled 
+//! with DrawLyonDesc::with_stencil. Pipelines are built lazily, for each combination 
+//! of texturing and stencil use that is drawn.
+//! 
+//! Each mesh is blended with its utils::BlendMode, alpha blending by default, with 
+//! a pipeline for each mode. Meshes are sorted by blend mode within their layer, 
+//! so that each pipeline is bound once per layer. Blend modes other than alpha 
+//! blending expect premultiplied colours, which the fragment shaders output.
+//! 
+//! Meshes with a utils::ScissorRect are clipped to that rectangle of the screen, 
+//! which is much cheaper than a clip mask. The scissor is dynamic pipeline state, 
+//! set for each draw, and covers the whole framebuffer for other meshes.
 //! 
 //! Copyright © 2020 Benedict Gaster. All rights reserved.
 //! 
 use amethyst::{
+    assets::{AssetStorage, Handle},
     core::{
         ecs::{
-            Join, ReadStorage, SystemData, World,
+            hibitset::BitSetOr, storage::ComponentEvent, Entities, Entity, Join, Read, ReadStorage, ReaderId,
+            SystemData, World,
         },
-        math::{Vector2},
+        math::{Matrix4, Vector2, Vector3, Vector4},
+        transform::Transform,
+        Hidden, HiddenPropagate,
     },
     prelude::*,
     renderer::{
@@ -29,16 +84,27 @@ use amethyst::{
             mesh::{AsVertex},
             shader::{Shader, SpirvShader},
         },
-        submodules::{DynamicIndexBuffer, DynamicVertexBuffer},
+        submodules::{DynamicIndexBuffer, DynamicVertexBuffer, TextureId, TextureSub},
         types::Backend,
-        util, ChangeDetection,
+        resources::Tint,
+        util, ActiveCamera, Camera, ChangeDetection,
     },
+    ui::UiTransform,
     window::ScreenDimensions,
 };
 
 use derivative::Derivative;
 
-use crate::utils::{Mesh, CustomArgs, PushConstant, ActiveMesh};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{Bound, RangeBounds},
+};
+
+use crate::utils::{Mesh, VertexType, CustomArgs, InstanceArgs, ActiveMesh, MeshFilter, MeshTag, WorldSpace, LyonLayer, IndexType, ClipMask, ScissorRect, BlendMode, InstanceOf,
+    CoordinateMode, CoordinateSystem, Origin, Units};
+use crate::texture::{TextureFill, TextureMode};
+use crate::assets::LyonMesh;
+use crate::gradient::Gradient;
 
 // Load SPIV shaders
 // Note: Shaders are pre-built using build.rs and just load binaries.
@@ -54,17 +120,68 @@ lazy_static::lazy_static! {
         ShaderStageFlags::FRAGMENT,
         "main",
     ).unwrap();
+
+    static ref TEXTURE_FRAGMENT: SpirvShader = SpirvShader::from_bytes(
+        include_bytes!("../assets/gen/shaders/lyon_texture.frag.spv"),
+        ShaderStageFlags::FRAGMENT,
+        "main",
+    ).unwrap();
+
+    static ref MASK_FRAGMENT: SpirvShader = SpirvShader::from_bytes(
+        include_bytes!("../assets/gen/shaders/lyon_mask.frag.spv"),
+        ShaderStageFlags::FRAGMENT,
+        "main",
+    ).unwrap();
 }
 
 #[derive(Clone, Debug, PartialEq, Derivative)]
 #[derivative(Default(bound = ""))]
-pub struct DrawLyonDesc;
+pub struct DrawLyonDesc {
+    stencil: bool,
+    blend: BlendMode,
+    #[derivative(Default(value = "true"))]
+    anti_alias: bool,
+    mode: CoordinateMode,
+    layers: Option<(Bound<i32>, Bound<i32>)>,
+}
 
 impl DrawLyonDesc {
     /// Create instance of `DrawLyonDesc` render group
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Enable clip masks, which are drawn into the stencil buffer. The target must 
+    /// have a depth-stencil attachment, e.g. of format D24UnormS8Uint, which is 
+    /// cleared to 0 each frame, as window::RenderToWindowWithStencil creates.
+    pub fn with_stencil(mut self, stencil: bool) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
+    /// Set the blend mode of meshes without a BlendMode component
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Set whether anti-aliased strokes and fringes are feathered
+    pub fn with_anti_alias(mut self, anti_alias: bool) -> Self {
+        self.anti_alias = anti_alias;
+        self
+    }
+
+    /// Set the space meshes without a WorldSpace marker are drawn in
+    pub fn with_coordinate_mode(mut self, mode: CoordinateMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Draw only meshes on layers within the bounds, or all meshes if None
+    pub fn with_layers(mut self, layers: Option<(Bound<i32>, Bound<i32>)>) -> Self {
+        self.layers = layers;
+        self
+    }
 }
 
 impl<B: Backend> RenderGroupDesc<B, World> for DrawLyonDesc {
@@ -73,57 +190,272 @@ impl<B: Backend> RenderGroupDesc<B, World> for DrawLyonDesc {
         _ctx: &GraphContext<B>,
         factory: &mut Factory<B>,
         _queue: QueueId,
-        _world: &World,
+        world: &World,
         framebuffer_width: u32,
         framebuffer_height: u32,
-        subpass: hal::pass::Subpass<'_, B>,
+        _subpass: hal::pass::Subpass<'_, B>,
         _buffers: Vec<NodeBuffer>,
         _images: Vec<NodeImage>,
     ) -> Result<Box<dyn RenderGroup<B, World>>, failure::Error> {
         let vertex = DynamicVertexBuffer::new();
+        let instance = DynamicVertexBuffer::new();
         let index = DynamicIndexBuffer::new();
+        let mesh_reader = world.write_storage::<Mesh>().register_reader();
+        let textures = TextureSub::new(factory)?;
 
-        let (pipeline, pipeline_layout) = build_custom_pipeline(
-            factory,
-            subpass,
-            framebuffer_width,
-            framebuffer_height,
-            Vec::new(),
-        )?;
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(vec![textures.raw_layout()], None as Option<(_, _)>)
+        }?;
 
         Ok(Box::new(DrawCustom::<B> {
-            pipeline,
+            pipelines: HashMap::new(),
             pipeline_layout,
+            framebuffer_width,
+            framebuffer_height,
+            stencil: self.stencil,
+            warned_stencil: false,
+            blend: self.blend,
+            anti_alias: self.anti_alias,
+            mode: self.mode,
+            layers: self.layers,
+            textures,
             vertex,
+            instance,
             index,
-            vertex_count: 0,
-            index_count: 0,
+            vertices: Vec::new(),
+            instances: Vec::new(),
+            indices: Vec::new(),
+            order: Vec::new(),
+            bounds: HashMap::new(),
+            generation: 0,
+            uploaded: Vec::new(),
+            mesh_reader,
             change: Default::default(),
-            constant: PushConstant::default(),
             commands: Vec::new(),
         }))
     }
 }
 
+/// Stencil value of the pixels inside the current clip mask, the stencil buffer is 0 elsewhere
+const STENCIL_CLIP: u32 = 1;
+
+/// Use of the stencil buffer by a draw, for clip masks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Stencil {
+    /// Drawn without a stencil test
+    None,
+    /// Draws a clip mask into the stencil buffer, as STENCIL_CLIP
+    Write,
+    /// Draws the previous clip mask into the stencil buffer again, as 0
+    Clear,
+    /// Drawn where the stencil buffer equals STENCIL_CLIP
+    Test,
+}
+
+/// Bottom-left and top-right corners of a mesh's bounds
+type Bounds = (Vector2<f32>, Vector2<f32>);
+
+/// Where the geometry of a draw comes from, an entity's Mesh or a LyonMesh asset, by handle id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Source {
+    Entity(Entity),
+    Asset(u32),
+}
+
+/// Identity of the geometry of a source, so that geometry replaced without a Mesh edit, i.e. 
+/// an asset that is hot-reloaded, or a new asset reusing a dropped handle's id, is rebuilt. 
+/// A replacement asset is loaded while the one it replaces is still alive, so the address 
+/// of its vertices differs, and the counts catch a new asset reusing the old one's memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct GeometryKey {
+    source: Source,
+    address: usize,
+    vertex_count: u32,
+    index_count: u32,
+}
+
+/// Geometry of a draw
+#[derive(Clone, Copy, Debug)]
+enum MeshRef<'a> {
+    Component(&'a Mesh),
+    Asset(&'a LyonMesh),
+}
+
+impl<'a> MeshRef<'a> {
+    fn args(&self) -> Vec<CustomArgs> {
+        match self {
+            MeshRef::Component(m) => m.get_args(),
+            MeshRef::Asset(m) => m.get_args(),
+        }
+    }
+
+    fn vertices(&self) -> &'a [VertexType] {
+        match self {
+            MeshRef::Component(m) => &m.vertices,
+            MeshRef::Asset(m) => &m.vertices,
+        }
+    }
+
+    fn vertex_count(&self) -> u32 {
+        self.vertices().len() as u32
+    }
+
+    fn key(&self, source: Source) -> GeometryKey {
+        GeometryKey {
+            source,
+            address: self.vertices().as_ptr() as usize,
+            vertex_count: self.vertex_count(),
+            index_count: self.indices().len() as u32,
+        }
+    }
+
+    fn indices(&self) -> &'a [IndexType] {
+        match self {
+            MeshRef::Component(m) => &m.indices,
+            MeshRef::Asset(m) => &m.indices,
+        }
+    }
+
+    fn scale(&self) -> Vector2<f32> {
+        match self {
+            MeshRef::Component(m) => m.scale,
+            MeshRef::Asset(m) => m.scale,
+        }
+    }
+
+    fn gradient(&self) -> Option<&'a Gradient> {
+        match self {
+            MeshRef::Component(m) => m.gradient.as_ref(),
+            MeshRef::Asset(m) => m.gradient.as_ref(),
+        }
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        match self {
+            MeshRef::Component(m) => m.bounds(),
+            MeshRef::Asset(m) => m.bounds(),
+        }
+    }
+}
+
+/// Mesh to be drawn by an entity, with what it is sorted by
+struct DrawItem<'a> {
+    layer: LyonLayer,
+    /// global z of the UI element placing the mesh, or 0
+    z: f32,
+    blend: BlendMode,
+    entity: Entity,
+    source: Source,
+    mesh: MeshRef<'a>,
+    /// from mesh coords, with scale, to homogenous coords
+    transform: Matrix4<f32>,
+}
+
+/// Pipeline state that differs between draws
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    textured: bool,
+    blend: BlendMode,
+    stencil: Stencil,
+}
+
 /// Draw commands to be rendered as individual draw calls
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct DrawCmdOps {
 	vertex_range: std::ops::Range<u32>,
     index_range: std::ops::Range<u32>,
-    scale: Vector2<f32>,
+    /// range of the instance buffer to draw, each instance has the transform from mesh coords 
+    /// to homogenous coords, i.e. the projection (screen or camera), the entity's global 
+    /// transform and the mesh scale combined, the tint multiplied with the colour of each 
+    /// vertex, and the mesh's gradient or texture
+    instances: std::ops::Range<u32>,
+    /// texture filling the mesh, if any
+    texture: Option<TextureId>,
+    blend: BlendMode,
+    stencil: Stencil,
+    /// scissor rectangle, in framebuffer pixels
+    scissor: pso::Rect,
+}
+
+impl DrawCmdOps {
+    /// Draw geometry, with one instance to be set by push_draw
+    fn new(vertex_range: std::ops::Range<u32>, index_range: std::ops::Range<u32>) -> Self {
+        Self {
+            vertex_range,
+            index_range,
+            instances: 0..0,
+            texture: None,
+            blend: BlendMode::default(),
+            stencil: Stencil::None,
+            scissor: pso::Rect { x: 0, y: 0, w: 0, h: 0 },
+        }
+    }
+
+    /// If other draws the same geometry with the same state, so can be drawn as more instances
+    fn batches_with(&self, other: &DrawCmdOps) -> bool {
+        self.vertex_range == other.vertex_range
+            && self.index_range == other.index_range
+            && self.texture == other.texture
+            && self.blend == other.blend
+            && self.stencil == other.stencil
+            && self.scissor == other.scissor
+    }
+
+    fn pipeline_key(&self) -> PipelineKey {
+        PipelineKey {
+            textured: self.texture.is_some(),
+            // clip masks are not drawn to the colour target, so aren't blended
+            blend: match self.stencil {
+                Stencil::Write | Stencil::Clear => BlendMode::default(),
+                Stencil::None | Stencil::Test => self.blend,
+            },
+            stencil: self.stencil,
+        }
+    }
 }
 
 /// Draws meshes to the screen.
 #[derive(Debug)]
 pub struct DrawCustom<B: Backend> {
-    pipeline: B::GraphicsPipeline,
+    /// pipelines built so far, or None if building the pipeline failed
+    pipelines: HashMap<PipelineKey, Option<B::GraphicsPipeline>>,
     pipeline_layout: B::PipelineLayout,
+    framebuffer_width: u32,
+    framebuffer_height: u32,
+    /// if clip masks are enabled
+    stencil: bool,
+    warned_stencil: bool,
+    /// blend mode of meshes without a BlendMode
+    blend: BlendMode,
+    anti_alias: bool,
+    /// space of meshes without a WorldSpace marker
+    mode: CoordinateMode,
+    /// layers drawn, or None for all
+    layers: Option<(Bound<i32>, Bound<i32>)>,
+    textures: TextureSub<B>,
     vertex: DynamicVertexBuffer<B, CustomArgs>,
-    index: DynamicIndexBuffer<B, u16>,
-    vertex_count: usize,
-    index_count: usize,
+    instance: DynamicVertexBuffer<B, InstanceArgs>,
+    index: DynamicIndexBuffer<B, IndexType>,
+    /// geometry of all meshes drawn, and their clip masks, kept so that it can be 
+    /// uploaded to each frame's buffers
+    vertices: Vec<CustomArgs>,
+    indices: Vec<IndexType>,
+    /// per draw arguments, one instance for each mesh or instance drawn
+    instances: Vec<InstanceArgs>,
+    /// meshes and assets, in draw order followed by clip masks, that the geometry was built 
+    /// from. Those drawn more than once, by instances or shared assets, appear once.
+    order: Vec<GeometryKey>,
+    /// bounds of the meshes and assets drawn with a stretched texture, cleared when the 
+    /// geometry is rebuilt
+    bounds: HashMap<Source, Option<Bounds>>,
+    /// incremented each time the geometry is rebuilt
+    generation: u64,
+    /// generation of the geometry uploaded to each frame's buffers
+    uploaded: Vec<Option<u64>>,
+    mesh_reader: ReaderId<ComponentEvent>,
     change: ChangeDetection,
-    constant: PushConstant,
     commands: Vec<DrawCmdOps>,
 }
 
@@ -133,82 +465,347 @@ impl<B: Backend> RenderGroup<B, World> for DrawCustom<B> {
         factory: &Factory<B>,
         _queue: QueueId,
         index: usize,
-        _subpass: hal::pass::Subpass<'_, B>,
+        subpass: hal::pass::Subpass<'_, B>,
         world: &World,
     ) -> PrepareResult {
-        let (mesh,) = <(ReadStorage<'_, Mesh>,)>::fetch(world);
+        let (entities, mesh, handles, assets, instance_of, tags, transforms, ui_transforms, tints, texture_fills, clip_masks, scissors, blend_modes, world_space, layers, hidden, hidden_propagate) = <(
+            Entities<'_>,
+            ReadStorage<'_, Mesh>,
+            ReadStorage<'_, Handle<LyonMesh>>,
+            Read<'_, AssetStorage<LyonMesh>>,
+            ReadStorage<'_, InstanceOf>,
+            ReadStorage<'_, MeshTag>,
+            ReadStorage<'_, Transform>,
+            ReadStorage<'_, UiTransform>,
+            ReadStorage<'_, Tint>,
+            ReadStorage<'_, TextureFill>,
+            ReadStorage<'_, ClipMask>,
+            ReadStorage<'_, ScissorRect>,
+            ReadStorage<'_, BlendMode>,
+            ReadStorage<'_, WorldSpace>,
+            ReadStorage<'_, LyonLayer>,
+            ReadStorage<'_, Hidden>,
+            ReadStorage<'_, HiddenPropagate>,
+        )>::fetch(world);
+
+        // any edit to a mesh is reported by its flagged storage, so the geometry
+        // is only rebuilt when something has actually changed
+        let mesh_changed = mesh.channel().read(&mut self.mesh_reader).count() > 0;
         
         let screen_dimensions = world.read_resource::<ScreenDimensions>();
         let (width, height) = {
             (screen_dimensions.width(), screen_dimensions.height())
         };
 
-        // setup scaling from screen space to homgenous coords (including HIDPI scaling)
+        // setup scaling from screen space to homgenous coords, in the coordinate system's 
+        // origin and units (including HIDPI scaling)
         let hidpi = screen_dimensions.hidpi_factor() as f32;
-        self.constant
-			.set_scale(Vector2::new(hidpi * (2.0 / width), hidpi * (2.0 / height)));
-        self.constant.set_translation(Vector2::new(-1.0, -1.0));
+        let coordinates = *world.read_resource::<CoordinateSystem>();
+        let projection = coordinates.projection(width, height, hidpi);
+
+        // world space meshes are drawn through the camera, and skipped if there is no camera
+        let camera = camera_projection(world);
 
-        //Update vertex count and see if it has changed
-        let old_vertex_count = self.vertex_count;
-        let old_index_count = self.index_count;
+        let (mode, default_blend, anti_alias) = (self.mode, self.blend, self.anti_alias);
+        let layer_range = self.layers;
 
-        let mut index_range = std::ops::Range::<u32> { start: 0, end: 0 };
+        // transform of an entity's mesh, with scale, from mesh coords to homogenous coords
+        let mesh_transform = |entity: Entity, scale: Vector2<f32>| -> Option<Matrix4<f32>> {
+            let scaling = Matrix4::new_nonuniform_scaling(&Vector3::new(scale.x, scale.y, 1.0));
+            if let Some(ui) = ui_transforms.get(entity).filter(|_| mode == CoordinateMode::Ui) {
+                return Some(ui_projection(ui, width, height) * scaling);
+            }
+            let projection = if mode == CoordinateMode::World || world_space.contains(entity) {
+                camera?
+            } else {
+                projection
+            };
+            // meshes without a transform are drawn in place
+            let model = transforms.get(entity).map_or_else(Matrix4::identity, |t| *t.global_matrix());
+            Some(projection * model * scaling)
+        };
+
+        // geometry of an entity, its own Mesh, or else its LyonMesh asset once loaded
+        let mesh_of = |entity: Entity| {
+            if let Some(m) = mesh.get(entity) {
+                return Some((Source::Entity(entity), MeshRef::Component(m)));
+            }
+            let handle = handles.get(entity)?;
+            assets.get(handle).map(|m| (Source::Asset(handle.id()), MeshRef::Asset(m)))
+        };
 
-        let mut vertices = Vec::with_capacity(self.vertex_count as usize);
-		let mut indices = Vec::with_capacity(self.index_count as usize);
+        let old_commands = std::mem::take(&mut self.commands);
+        let old_instances = std::mem::take(&mut self.instances);
 
-        // if ActiveMesh is set, then we render only that mesh
+        // if ActiveMesh is set, then we render only that mesh, otherwise render all meshes.
+        // In either case meshes must pass the MeshFilter, and hidden meshes are skipped.
+        // Instances are drawn with the mesh of the entity they are an instance of, which 
+        // is found as source, but otherwise are filtered and drawn as their own entity.
         let active_mesh = world.read_resource::<ActiveMesh>();
-        if let Some(mesh_entity) = active_mesh.entity {
-            let mesh_read = world.read_storage::<Mesh>();
-            if let Some(m) = mesh_read.get(mesh_entity) {
-                self.vertex_count = m.vertices.len();
-                self.index_count = m.indices.len();
-
-                index_range.start = index_range.end;
-                index_range.end += m.indices.len() as u32;
-
-                self.commands.push(DrawCmdOps {
-                    vertex_range: std::ops::Range {
-                        start: vertices.len() as u32,
-                        end: (vertices.len() + m.vertices.len()) as u32,
-                    },
-                    index_range: index_range.clone(),
-                    scale: m.scale,
-                });
-
-                vertices.extend(m.get_args().iter().map(|v| (*v)).collect::<Vec<CustomArgs>>());
-                indices.extend(m.indices.iter().map(|v| (*v).into()).collect::<Vec<u16>>());
+        let filter = world.read_resource::<MeshFilter>();
+        let drawable = BitSetOr(BitSetOr(mesh.mask(), handles.mask()), instance_of.mask());
+        let mut meshes: Vec<DrawItem<'_>> = (
+            &entities,
+            drawable,
+            tags.maybe(),
+            layers.maybe(),
+            blend_modes.maybe(),
+            !&hidden,
+            !&hidden_propagate,
+        )
+        .join()
+        .filter(|(entity, _, tag, _, _, _, _)| {
+            active_mesh.entity.is_none_or(|active| active == *entity) && filter.accepts(*entity, *tag)
+        })
+        .filter_map(|(entity, _, _, layer, blend, _, _)| {
+            // an entity's own mesh takes precedence over the one it is an instance of
+            let (source, m) = mesh_of(entity)
+                .or_else(|| instance_of.get(entity).and_then(|InstanceOf(target)| mesh_of(*target)))?;
+            let layer = layer.copied().unwrap_or_default();
+            if !layer_range.as_ref().is_none_or(|range| range.contains(&layer.0)) {
+                return None;
             }
+            let blend = blend.copied().unwrap_or(default_blend);
+            // UI meshes are ordered by their element's depth within their layer
+            let z = ui_transforms.get(entity).filter(|_| mode == CoordinateMode::Ui).map_or(0.0, |ui| ui.global_z());
+            mesh_transform(entity, m.scale()).map(|transform| DrawItem {
+                layer,
+                z,
+                blend,
+                entity,
+                source,
+                mesh: m,
+                transform,
+            })
+        })
+        .collect();
+
+        // sort into painter's order, grouping each layer by blend mode so that each blend mode's 
+        // pipeline is bound once per layer (and UI depth), the sort is stable so meshes on the 
+        // same layer with the same blend mode stay in entity order
+        meshes.sort_by(|a, b| {
+            a.layer
+                .cmp(&b.layer)
+                .then(a.z.partial_cmp(&b.z).unwrap_or(std::cmp::Ordering::Equal))
+                .then(a.blend.cmp(&b.blend))
+        });
+
+        // each mesh drawn is included once, however many instances of it there are, and 
+        // clip masks are drawn whether or not their own mesh is drawn, so their geometry 
+        // follows that of the meshes drawn
+        let mut order = Vec::new();
+        let mut geometry = Vec::new();
+        let mut included = HashSet::new();
+        for item in &meshes {
+            if included.insert(item.source) {
+                order.push(item.mesh.key(item.source));
+                geometry.push(item.mesh);
+            }
+        }
+        if self.stencil {
+            for item in &meshes {
+                if let Some(ClipMask(mask)) = clip_masks.get(item.entity) {
+                    if let Some((source, m)) = mesh_of(*mask) {
+                        if included.insert(source) {
+                            order.push(m.key(source));
+                            geometry.push(m);
+                        }
+                    }
+                }
+            }
+        }
+
+        // the geometry must also be rebuilt if the set, or order, of meshes drawn has changed, 
+        // or if an asset has been replaced
+        let rebuild = mesh_changed || order != self.order;
+        if rebuild {
+            self.vertices.clear();
+            self.indices.clear();
+            self.bounds.clear();
         }
-        else {
-            // ActiveMesh not set, so render all meshs
-            self.vertex_count = mesh.join().fold(0, |sum, mesh| sum + mesh.vertices.len());
-            self.index_count = mesh.join().fold(0, |sum, mesh| sum + mesh.indices.len());
-
-            for m in mesh.join() {
-                index_range.start = index_range.end;
-                index_range.end += m.indices.len() as u32;
-                
-                self.commands.push(DrawCmdOps {
-                    vertex_range: std::ops::Range {
-                        start: vertices.len() as u32,
-                        end: (vertices.len() + m.vertices.len()) as u32,
-                    },
-                    index_range: index_range.clone(),
-                    scale: m.scale,
-                });
-
-                vertices.extend(m.get_args().iter().map(|v| (*v)).collect::<Vec<CustomArgs>>());
-                indices.extend(m.indices.iter().map(|v| (*v).into()).collect::<Vec<u16>>());
+
+        let mut ranges = HashMap::with_capacity(order.len());
+        let mut vertex_start = 0;
+        let mut index_start = 0;
+        for (key, m) in order.iter().zip(&geometry) {
+            let vertex_end = vertex_start + m.vertex_count();
+            let index_end = index_start + m.indices().len() as u32;
+            ranges.insert(key.source, (vertex_start..vertex_end, index_start..index_end));
+            vertex_start = vertex_end;
+            index_start = index_end;
+
+            if rebuild {
+                self.vertices.extend(m.args());
+                self.indices.extend(m.indices().iter().copied());
             }
         }
+        self.order = order;
+
+        // meshes without a scissor rectangle, and clip masks, are drawn to the whole framebuffer
+        let full_scissor = pso::Rect {
+            x: 0,
+            y: 0,
+            w: self.framebuffer_width as i16,
+            h: self.framebuffer_height as i16,
+        };
+
+        let mut textures_changed = false;
+        // mask currently in the stencil buffer, with the geometry and transform it was drawn with
+        let mut clip: Option<(Entity, DrawCmdOps, InstanceArgs)> = None;
+
+        for DrawItem { blend, entity, source, mesh: m, transform, .. } in meshes {
+            let (vertex_range, index_range) = ranges[&source].clone();
+
+            let args = InstanceArgs::new(
+                transform,
+                tints.get(entity).map_or(Vector4::new(1.0, 1.0, 1.0, 1.0), |t| {
+                    let (r, g, b, a) = t.0.into_components();
+                    Vector4::new(r, g, b, a)
+                }),
+            );
+
+            // a texture fill takes the place of the mesh's gradient
+            let (args, texture) = match texture_fills.get(entity) {
+                Some(fill) => {
+                    // meshes are not drawn until their texture has loaded
+                    let (texture, changed) = match self.textures.insert(
+                        factory,
+                        world,
+                        &fill.texture,
+                        hal::image::Layout::ShaderReadOnlyOptimal,
+                    ) {
+                        Some(texture) => texture,
+                        None => continue,
+                    };
+                    textures_changed |= changed;
+
+                    let (origin, size) = match fill.mode {
+                        // finding the bounds scans every vertex, so is only done once per geometry
+                        TextureMode::Stretch => match *self.bounds.entry(source).or_insert_with(|| m.bounds()) {
+                            Some((min, max)) => ([min.x, min.y], [max.x - min.x, max.y - min.y]),
+                            None => ([0.0, 0.0], [1.0, 1.0]),
+                        },
+                        TextureMode::Repeat | TextureMode::Clamp => (fill.origin, fill.size),
+                    };
+                    (args.with_texture(fill.mode, origin, size), Some(texture))
+                }
+                None => match m.gradient() {
+                    Some(gradient) => (args.with_gradient(gradient), None),
+                    None => (args, None),
+                },
+            };
+
+            let stencil = match clip_masks.get(entity) {
+                None => Stencil::None,
+                Some(_) if !self.stencil => {
+                    if !self.warned_stencil {
+                        log::warn!("ClipMask requires the Lyon pass to be built with a stencil buffer, drawing meshes unclipped");
+                        self.warned_stencil = true;
+                    }
+                    Stencil::None
+                }
+                Some(ClipMask(mask)) => {
+                    if clip.as_ref().map(|(current, _, _)| current) != Some(mask) {
+                        // clipped meshes are not drawn if their mask cannot be
+                        let (mask_mesh, (mask_vertices, mask_indices)) = match mesh_of(*mask) {
+                            Some((mask_source, mask_mesh)) => match ranges.get(&mask_source) {
+                                Some(range) => (mask_mesh, range.clone()),
+                                None => continue,
+                            },
+                            None => continue,
+                        };
+                        let mask_transform = match mesh_transform(*mask, mask_mesh.scale()) {
+                            Some(transform) => transform,
+                            None => continue,
+                        };
+
+                        // the stencil buffer is cleared to 0 each frame, and the previous mask is 
+                        // cleared by drawing it again, covering exactly the pixels it set
+                        if let Some((_, previous, args)) = clip.take() {
+                            push_draw(
+                                &mut self.commands,
+                                &mut self.instances,
+                                DrawCmdOps { stencil: Stencil::Clear, ..previous },
+                                args,
+                            );
+                        }
 
-        let changed = old_vertex_count != self.vertex_count || old_index_count != self.index_count;
+                        let draw = DrawCmdOps {
+                            scissor: full_scissor,
+                            ..DrawCmdOps::new(mask_vertices, mask_indices)
+                        };
+                        let args = InstanceArgs::new(mask_transform, Vector4::new(1.0, 1.0, 1.0, 1.0));
+                        push_draw(
+                            &mut self.commands,
+                            &mut self.instances,
+                            DrawCmdOps { stencil: Stencil::Write, ..draw.clone() },
+                            args,
+                        );
+                        clip = Some((*mask, draw, args));
+                    }
+                    Stencil::Test
+                }
+            };
 
-        self.vertex.write(factory, index, vertices.len() as u64, &[vertices.iter()]);
-        self.index.write(factory, index, indices.len() as u64, &[indices.iter()]);
+            let (framebuffer_width, framebuffer_height) = (self.framebuffer_width, self.framebuffer_height);
+            let scissor = scissors.get(entity).map_or(full_scissor, |rect| {
+                framebuffer_rect(rect, &coordinates, width, height, hidpi, framebuffer_width, framebuffer_height)
+            });
+
+            push_draw(
+                &mut self.commands,
+                &mut self.instances,
+                DrawCmdOps {
+                    texture,
+                    blend,
+                    stencil,
+                    scissor,
+                    ..DrawCmdOps::new(vertex_range, index_range)
+                },
+                args.with_blend(blend).with_anti_alias(anti_alias),
+            );
+        }
+
+        self.textures.maintain(factory, world);
+
+        // build any pipelines that are needed for the first time
+        for key in self.commands.iter().map(DrawCmdOps::pipeline_key) {
+            if !self.pipelines.contains_key(&key) {
+                let pipeline = build_custom_pipeline(
+                    factory,
+                    subpass,
+                    self.framebuffer_width,
+                    self.framebuffer_height,
+                    &self.pipeline_layout,
+                    key,
+                )
+                .map_err(|e| log::error!("Failed to build Lyon pipeline {:?}: {}", key, e))
+                .ok();
+                self.pipelines.insert(key, pipeline);
+            }
+        }
+
+        if rebuild {
+            self.generation += 1;
+        }
+
+        // each frame in flight has its own buffers, so only upload to this frame's buffers
+        // if they are out of date
+        if self.uploaded.len() <= index {
+            self.uploaded.resize(index + 1, None);
+        }
+        if self.uploaded[index] != Some(self.generation) {
+            self.vertex.write(factory, index, self.vertices.len() as u64, &[self.vertices.iter()]);
+            self.index.write(factory, index, self.indices.len() as u64, &[self.indices.iter()]);
+            self.uploaded[index] = Some(self.generation);
+        }
+
+        // the per draw arguments are small, so are simply written every frame
+        self.instance.write(factory, index, self.instances.len() as u64, &[self.instances.iter()]);
+
+        // transforms, tints and gradients can change without the geometry changing, so the draw commands 
+        // and their instances are compared too
+        let changed = rebuild || textures_changed || old_commands != self.commands || old_instances != self.instances;
 
         // Return with we can reuse the draw buffers using the utility struct ChangeDetection
         self.change.prepare_result(index, changed)
@@ -222,45 +819,58 @@ impl<B: Backend> RenderGroup<B, World> for DrawCustom<B> {
         _world: &World,
     ) {
         // Don't worry about drawing if there are no vertices. Like before the state adds them to the screen.
-        if self.vertex_count == 0 {
+        if self.vertices.is_empty() {
             return;
         }
 
-        // Bind the pipeline to the the encoder
-        let layout = &self.pipeline_layout;
-        encoder.bind_graphics_pipeline(&self.pipeline);
-
-        // Bind the vertex buffer to the encoder
+        // Bind the vertex and instance buffers to the encoder
         self.vertex.bind(index, 0, 0, &mut encoder);
+        self.instance.bind(index, 1, 0, &mut encoder);
 		self.index.bind(index, 0, &mut encoder);
 
+        let mut bound = None;
+        let mut scissor = None;
         for draw in &self.commands {
-            // Draw the vertices
-            unsafe {
-                let mut constant = self.constant.clone();
-                let scale = constant.scale();
-                constant.set_scale(Vector2::new(draw.scale.x * scale.x, draw.scale.y * scale.y));
-                encoder.push_constants(
-                    layout,
-                    pso::ShaderStageFlags::VERTEX,
-                    0,
-                    hal::memory::cast_slice::<f32, u32>(constant.raw()),
-                );
+            let key = draw.pipeline_key();
+            let pipeline = match self.pipelines.get(&key) {
+                Some(Some(pipeline)) => pipeline,
+                _ => continue,
+            };
 
+            // Bind the pipeline to the the encoder, only when it changes between draws
+            if bound != Some(key) {
+                encoder.bind_graphics_pipeline(pipeline);
+                bound = Some(key);
+            }
+            // the scissor is dynamic state, so must be set before the first draw
+            if scissor != Some(&draw.scissor) {
+                // SAFETY: every pipeline is built with a dynamic scissor for its one viewport, 
+                // and each rectangle is the framebuffer or clamped to it by framebuffer_rect
+                unsafe {
+                    encoder.set_scissors(0, std::iter::once(&draw.scissor));
+                }
+                scissor = Some(&draw.scissor);
+            }
+            if let Some(texture) = draw.texture {
+                self.textures.bind(&self.pipeline_layout, 0, texture, &mut encoder);
+            }
+
+            // Draw the vertices, once for each of this draw's instances
+            unsafe {
                 encoder.draw_indexed(
 					draw.index_range.clone(),
 					draw.vertex_range.start as i32,
-					std::ops::Range { start: 0, end: 1 },
+					draw.instances.clone(),
 				);
             }
         }
-
-        self.commands.clear();
     }
 
     fn dispose(self: Box<Self>, factory: &mut Factory<B>, _world: &World) {
         unsafe {
-            factory.device().destroy_graphics_pipeline(self.pipeline);
+            for pipeline in self.pipelines.into_values().flatten() {
+                factory.device().destroy_graphics_pipeline(pipeline);
+            }
             factory
                 .device()
                 .destroy_pipeline_layout(self.pipeline_layout);
@@ -268,37 +878,172 @@ impl<B: Backend> RenderGroup<B, World> for DrawCustom<B> {
     }
 }
 
+/// Adds draw, with an instance of args, to commands, as another instance of the last 
+/// command if it draws the same geometry with the same state
+fn push_draw(commands: &mut Vec<DrawCmdOps>, instances: &mut Vec<InstanceArgs>, mut draw: DrawCmdOps, args: InstanceArgs) {
+    let instance = instances.len() as u32;
+    instances.push(args);
+    match commands.last_mut() {
+        Some(last) if last.batches_with(&draw) && last.instances.end == instance => {
+            last.instances.end = instance + 1;
+        }
+        _ => {
+            draw.instances = instance..instance + 1;
+            commands.push(draw);
+        }
+    }
+}
+
+/// Returns the combined projection and view matrix of the active camera, or of the 
+/// first camera found if there is no active camera. 
+fn camera_projection(world: &World) -> Option<Matrix4<f32>> {
+    let (active_camera, cameras, transforms) = <(
+        Read<'_, ActiveCamera>,
+        ReadStorage<'_, Camera>,
+        ReadStorage<'_, Transform>,
+    )>::fetch(world);
+
+    let identity = Transform::default();
+    active_camera
+        .entity
+        .and_then(|entity| {
+            cameras
+                .get(entity)
+                .map(|camera| (camera, transforms.get(entity).unwrap_or(&identity)))
+        })
+        .or_else(|| {
+            (&cameras, transforms.maybe())
+                .join()
+                .map(|(camera, transform)| (camera, transform.unwrap_or(&identity)))
+                .next()
+        })
+        .map(|(camera, transform)| camera.matrix * transform.global_view_matrix())
+}
+
+/// Returns the transform from the units of a UI element, centred on it, to homogenous coords, 
+/// of a screen of physical width and height. UI elements are positioned in physical pixels, 
+/// from the bottom-left of the screen, as RenderUi draws them.
+fn ui_projection(ui: &UiTransform, width: f32, height: f32) -> Matrix4<f32> {
+    let ratio = |pixels: f32, size: f32| if size != 0.0 { pixels / size } else { 1.0 };
+    CoordinateSystem::new(Origin::BottomLeft, Units::Physical).projection(width, height, 1.0)
+        * Matrix4::new_translation(&Vector3::new(ui.pixel_x(), ui.pixel_y(), 0.0))
+        * Matrix4::new_nonuniform_scaling(&Vector3::new(
+            ratio(ui.pixel_width(), ui.width),
+            ratio(ui.pixel_height(), ui.height),
+            1.0,
+        ))
+}
+
+/// Converts rect, in screen coordinates of a screen of physical width and height, into a 
+/// rectangle of the framebuffer, whose pixels are physical and from the top-left corner. 
+/// The rectangle is clamped to the framebuffer.
+fn framebuffer_rect(
+    rect: &ScissorRect,
+    coordinates: &CoordinateSystem,
+    width: f32,
+    height: f32,
+    hidpi: f32,
+    framebuffer_width: u32,
+    framebuffer_height: u32,
+) -> pso::Rect {
+    let scale = coordinates.pixels_per_unit(width, height, hidpi);
+    let (near, far) = (rect.y * scale.y, (rect.y + rect.height) * scale.y);
+    let (top, bottom) = match coordinates.origin {
+        Origin::BottomLeft => (height - far, height - near),
+        Origin::TopLeft => (near, far),
+    };
+    let (width, height) = (framebuffer_width as f32, framebuffer_height as f32);
+    let left = (rect.x * scale.x).floor().max(0.0).min(width);
+    let right = ((rect.x + rect.width) * scale.x).ceil().max(left).min(width);
+    let top = top.floor().max(0.0).min(height);
+    let bottom = bottom.ceil().max(top).min(height);
+    pso::Rect {
+        x: left as i16,
+        y: top as i16,
+        w: (right - left) as i16,
+        h: (bottom - top) as i16,
+    }
+}
+
+/// Blend state of blend mode. All modes but Alpha expect premultiplied colours, and 
+/// composite alpha as premultiplied alpha blending does.
+fn blend_state(blend: BlendMode) -> pso::BlendState {
+    let add = |src, dst| pso::BlendOp::Add { src, dst };
+    let over = add(pso::Factor::One, pso::Factor::OneMinusSrcAlpha);
+    match blend {
+        BlendMode::Alpha => pso::BlendState::ALPHA,
+        BlendMode::PremultipliedAlpha => pso::BlendState::PREMULTIPLIED_ALPHA,
+        BlendMode::Additive => pso::BlendState {
+            color: add(pso::Factor::One, pso::Factor::One),
+            alpha: over,
+        },
+        // dst * (src + 1 - src alpha), i.e. dst * src where fully opaque
+        BlendMode::Multiply => pso::BlendState {
+            color: add(pso::Factor::DstColor, pso::Factor::OneMinusSrcAlpha),
+            alpha: over,
+        },
+        // src + dst * (1 - src)
+        BlendMode::Screen => pso::BlendState {
+            color: add(pso::Factor::One, pso::Factor::OneMinusSrcColor),
+            alpha: over,
+        },
+    }
+}
+
+/// Builds the pipeline for drawing meshes with the given texturing, blend mode and stencil use
 fn build_custom_pipeline<B: Backend>(
     factory: &Factory<B>,
     subpass: hal::pass::Subpass<'_, B>,
     framebuffer_width: u32,
     framebuffer_height: u32,
-    layouts: Vec<&B::DescriptorSetLayout>,
-) -> Result<(B::GraphicsPipeline, B::PipelineLayout), failure::Error> {
-
-    let pipeline_layout = unsafe {
-		factory
-			.device()
-			.create_pipeline_layout(layouts, &[(pso::ShaderStageFlags::VERTEX, 0..16)])
-	}?;
-
-    // Load the shaders
+    pipeline_layout: &B::PipelineLayout,
+    key: PipelineKey,
+) -> Result<B::GraphicsPipeline, failure::Error> {
+    // Load the shaders, clip masks only write to the stencil buffer so don't need paint
+    let fragment: &SpirvShader = match (key.stencil, key.textured) {
+        (Stencil::Write, _) | (Stencil::Clear, _) => &MASK_FRAGMENT,
+        (_, true) => &TEXTURE_FRAGMENT,
+        (_, false) => &FRAGMENT,
+    };
     let shader_vertex = unsafe { VERTEX.module(factory).unwrap() };
-    let shader_fragment = unsafe { FRAGMENT.module(factory).unwrap() };
+    let shader_fragment = unsafe { fragment.module(factory).unwrap() };
+
+    let stencil_test = |fun, op_pass| pso::StencilTest {
+        faces: pso::Sided::new(pso::StencilFace {
+            fun,
+            op_fail: pso::StencilOp::Keep,
+            op_depth_fail: pso::StencilOp::Keep,
+            op_pass,
+        }),
+        read_masks: pso::State::Static(pso::Sided::new(!0)),
+        write_masks: pso::State::Static(pso::Sided::new(!0)),
+        reference_values: pso::State::Static(pso::Sided::new(STENCIL_CLIP)),
+    };
+    let (stencil, blend) = match key.stencil {
+        Stencil::None => (None, Some(blend_state(key.blend))),
+        // masks are not drawn to the colour target
+        Stencil::Write => (Some(stencil_test(pso::Comparison::Always, pso::StencilOp::Replace)), None),
+        Stencil::Clear => (Some(stencil_test(pso::Comparison::Always, pso::StencilOp::Zero)), None),
+        Stencil::Test => (Some(stencil_test(pso::Comparison::Equal, pso::StencilOp::Keep)), Some(blend_state(key.blend))),
+    };
+    let mask = if blend.is_some() { pso::ColorMask::ALL } else { pso::ColorMask::empty() };
 
     // Build the pipeline
     let pipes = PipelinesBuilder::new()
         .with_pipeline(
             PipelineDescBuilder::new()
-                // This Pipeline uses our custom vertex description and does not use instancing
-                .with_vertex_desc(&[(CustomArgs::vertex(), pso::VertexInputRate::Vertex)])
+                // This Pipeline uses our custom vertex description, and one instance per draw
+                .with_vertex_desc(&[
+                    (CustomArgs::vertex(), pso::VertexInputRate::Vertex),
+                    (InstanceArgs::vertex(), pso::VertexInputRate::Instance(1)),
+                ])
                 .with_input_assembler(pso::InputAssemblerDesc::new(hal::Primitive::TriangleList))
                 // Add the shaders
                 .with_shaders(util::simple_shader_set(
                     &shader_vertex,
                     Some(&shader_fragment),
                 ))
-                .with_layout(&pipeline_layout)
+                .with_layout(pipeline_layout)
                 .with_subpass(subpass)
                 .with_framebuffer_size(framebuffer_width, framebuffer_height)
                 .with_baked_states(hal::pso::BakedStates {
@@ -311,14 +1056,17 @@ fn build_custom_pipeline<B: Backend>(
 						},
 						depth: 0.0..1.0,
 					}),
+					// the scissor is set for each draw, from the mesh's ScissorRect
 					scissor: None,
 					..Default::default()
 				})
-                // We are using alpha blending
-                .with_blend_targets(vec![pso::ColorBlendDesc {
-                    mask: pso::ColorMask::ALL,
-                    blend: Some(pso::BlendState::ALPHA),
-                }]),
+                .with_depth_stencil(pso::DepthStencilDesc {
+                    depth: None,
+                    depth_bounds: false,
+                    stencil,
+                })
+                // Blend with the mesh's blend mode
+                .with_blend_targets(vec![pso::ColorBlendDesc { mask, blend }]),
         )
         .build(factory, None);
 
@@ -328,14 +1076,109 @@ fn build_custom_pipeline<B: Backend>(
         factory.destroy_shader_module(shader_fragment);
     }
 
-    // Handle the Errors
-    match pipes {
-        Err(e) => {
-            unsafe {
-                factory.device().destroy_pipeline_layout(pipeline_layout);
-            }
-            Err(e)
+    pipes.map(|mut pipes| pipes.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Framebuffer rectangle of rect, on a screen of 800x600 physical pixels with a hidpi factor of 2
+    fn rect(origin: Origin, units: Units, rect: ScissorRect) -> pso::Rect {
+        framebuffer_rect(&rect, &CoordinateSystem::new(origin, units), 800.0, 600.0, 2.0, 800, 600)
+    }
+
+    #[test]
+    fn scissor_bottom_left() {
+        assert_eq!(
+            rect(Origin::BottomLeft, Units::Logical, ScissorRect::new(10.0, 20.0, 100.0, 50.0)),
+            pso::Rect { x: 20, y: 460, w: 200, h: 100 }
+        );
+    }
+
+    #[test]
+    fn scissor_top_left() {
+        assert_eq!(
+            rect(Origin::TopLeft, Units::Logical, ScissorRect::new(10.0, 20.0, 100.0, 50.0)),
+            pso::Rect { x: 20, y: 40, w: 200, h: 100 }
+        );
+    }
+
+    #[test]
+    fn scissor_units() {
+        assert_eq!(
+            rect(Origin::BottomLeft, Units::Physical, ScissorRect::new(10.0, 20.0, 100.0, 50.0)),
+            pso::Rect { x: 10, y: 530, w: 100, h: 50 }
+        );
+        assert_eq!(
+            rect(Origin::BottomLeft, Units::Normalised, ScissorRect::new(0.25, 0.5, 0.5, 0.5)),
+            pso::Rect { x: 200, y: 0, w: 400, h: 300 }
+        );
+        assert_eq!(
+            rect(Origin::TopLeft, Units::Normalised, ScissorRect::new(0.25, 0.5, 0.5, 0.5)),
+            pso::Rect { x: 200, y: 300, w: 400, h: 300 }
+        );
+    }
+
+    #[test]
+    fn scissor_matches_projection() {
+        // the viewport maps Vulkan's y of -1 to the top row of the framebuffer, so the 
+        // projected edges of the rectangle must be its top and bottom rows
+        for &origin in &[Origin::BottomLeft, Origin::TopLeft] {
+            let coordinates = CoordinateSystem::new(origin, Units::Logical);
+            let projection = coordinates.projection(800.0, 600.0, 2.0);
+            let row = |y: f32| ((projection * Vector4::new(0.0, y, 0.0, 1.0)).y + 1.0) / 2.0 * 600.0;
+            let (near, far) = (row(20.0), row(70.0));
+            let scissor = rect(origin, Units::Logical, ScissorRect::new(10.0, 20.0, 100.0, 50.0));
+            assert_eq!(
+                (scissor.y as f32, (scissor.y + scissor.h) as f32),
+                (near.min(far).round(), near.max(far).round())
+            );
         }
-        Ok(mut pipes) => Ok((pipes.remove(0), pipeline_layout)),
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ui_projection_matches_render_ui() {
+        use amethyst::ui::Anchor;
+
+        // RenderUi maps a pixel position (x, y), from the bottom-left of the screen, to 
+        // (2x / W - 1, 1 - 2y / H)
+        let ui = UiTransform::new("button".to_string(), Anchor::Middle, Anchor::Middle, 200.0, 150.0, 0.0, 100.0, 50.0);
+        let (width, height) = (800.0, 600.0);
+        let expected = |x: f32, y: f32| (2.0 * x / width - 1.0, 1.0 - 2.0 * y / height);
+        let project = |x: f32, y: f32| {
+            let p = ui_projection(&ui, width, height) * Vector4::new(x, y, 0.0, 1.0);
+            (p.x / p.w, p.y / p.w)
+        };
+        let close = |(ax, ay): (f32, f32), (ex, ey): (f32, f32)| (ax - ex).abs() < 1e-5 && (ay - ey).abs() < 1e-5;
+
+        // the element's centre, and its top-right corner, which is up the screen
+        assert!(close(project(0.0, 0.0), expected(200.0, 150.0)));
+        assert!(close(project(50.0, 25.0), expected(250.0, 175.0)));
+    }
+
+    #[test]
+    fn scissor_covers_partial_pixels() {
+        assert_eq!(
+            rect(Origin::TopLeft, Units::Physical, ScissorRect::new(0.5, 0.5, 1.0, 1.0)),
+            pso::Rect { x: 0, y: 0, w: 2, h: 2 }
+        );
+    }
+
+    #[test]
+    fn scissor_is_clamped_to_framebuffer() {
+        assert_eq!(
+            rect(Origin::BottomLeft, Units::Physical, ScissorRect::new(-10.0, -10.0, 1000.0, 1000.0)),
+            pso::Rect { x: 0, y: 0, w: 800, h: 600 }
+        );
+        // off screen, and negative sizes, are empty
+        assert_eq!(
+            rect(Origin::TopLeft, Units::Physical, ScissorRect::new(900.0, 700.0, 50.0, 50.0)),
+            pso::Rect { x: 800, y: 600, w: 0, h: 0 }
+        );
+        assert_eq!(
+            rect(Origin::TopLeft, Units::Physical, ScissorRect::new(100.0, 100.0, -50.0, -50.0)),
+            pso::Rect { x: 100, y: 100, w: 0, h: 0 }
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! Description:
+//!
+//! Path component, and the system that tessellates paths into meshes.
+//!
+//! Rather than hand tessellating a path into a Mesh, a LyonPath holding a
+//! lyon Path, and how it should be filled and/or stroked, can be added to an
+//! entity. The TessellatePathSystem then tessellates it into the entity's
+//! Mesh, whenever the path or its style changes.
+//!
+//! Tessellation tolerances are scaled by the on-screen size of a path, i.e. the
+//! physical pixels per unit of the CoordinateSystem, or of its UiTransform, and
+//! the scale of its Transform and Mesh, so curves stay smooth at any resolution
+//! and size. Paths are re-tessellated whenever the screen is resized, its hidpi
+//! factor changes, or the CoordinateSystem changes, and when their scale has
+//! doubled or halved.
+//!
+//! Fills and strokes are anti-aliased by default. Stroke vertices record which
+//! side of the stroke they are on, so that the fragment shader can feather
+//! the stroke's edges, and fills are surrounded by a thin fringe stroke that
+//! fades out from the fill's edge. The fringe is two pixels wide at the scale a
+//! path is tessellated for.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use amethyst::{
+    core::{
+        ecs::{
+            storage::ComponentEvent, BitSet, Component, DenseVecStorage, Entities, Entity, FlaggedStorage,
+            Join, Read, ReadStorage, ReaderId, System, SystemData, World, WriteStorage,
+        },
+        math::Vector2,
+        transform::Transform,
+    },
+    ui::UiTransform,
+    window::ScreenDimensions,
+};
+
+use lyon::{
+    math::Point,
+    path::Path,
+    tessellation::{
+        BuffersBuilder, FillAttributes, FillOptions, FillTessellator, Side, StrokeAttributes,
+        StrokeOptions, StrokeTessellator, TessellationError, VertexBuffers,
+    },
+};
+
+use std::collections::HashMap;
+
+use crate::gradient::Gradient;
+use crate::utils::{
+    CoordinateSystem, IndexType, Mesh, VertexType, WorldSpace, AA_FRINGE, AA_NONE, AA_STROKE, AA_UNPAINTED,
+};
+
+/// Width, in physical pixels, of the fringe stroked around anti-aliased fills, which is 
+/// divided by the pixels per unit a fill is tessellated for. The fringe fades out over 
+/// a pixel, so it only needs to be wide enough to cover one.
+pub const AA_FRINGE_WIDTH: f32 = 2.0;
+
+/// Geometry produced by tessellating a path
+pub type Geometry = VertexBuffers<VertexType, IndexType>;
+
+/// Fill style of a path
+#[derive(Clone, Debug, PartialEq)]
+pub struct FillStyle {
+    /// Lyon fill options, e.g. tolerance and fill rule
+    pub options: FillOptions,
+    /// Colour of the fill, multiplied with the gradient if there is one
+    pub colour: [f32; 4],
+    /// Gradient of the fill, if any. The gradient is stored on the mesh, and paints 
+    /// the fill, while the stroke of the same path keeps its own colour.
+    pub gradient: Option<Gradient>,
+    /// Anti-alias the edges of the fill, with a fringe. On by default, but the fringe 
+    /// slightly darkens the edges of translucent fills.
+    pub anti_alias: bool,
+}
+
+impl FillStyle {
+    /// Create a fill of colour, with default options
+    pub fn new(colour: [f32; 4]) -> Self {
+        Self {
+            options: FillOptions::default(),
+            colour,
+            gradient: None,
+            anti_alias: true,
+        }
+    }
+
+    /// Create a fill of gradient, with default options
+    pub fn gradient(gradient: Gradient) -> Self {
+        Self::new([1.0, 1.0, 1.0, 1.0]).with_gradient(gradient)
+    }
+
+    /// Set the gradient of the fill
+    pub fn with_gradient(mut self, gradient: Gradient) -> Self {
+        self.gradient = Some(gradient);
+        self
+    }
+
+    /// Set the fill options
+    pub fn with_options(mut self, options: FillOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set whether the fill is anti-aliased
+    pub fn with_anti_alias(mut self, anti_alias: bool) -> Self {
+        self.anti_alias = anti_alias;
+        self
+    }
+}
+
+/// Stroke style of a path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle {
+    /// Lyon stroke options, e.g. tolerance, line width, joins and caps
+    pub options: StrokeOptions,
+    /// Colour of the stroke
+    pub colour: [f32; 4],
+    /// Anti-alias the edges of the stroke. On by default, but strokes narrower than 
+    /// a pixel fade out.
+    pub anti_alias: bool,
+}
+
+impl StrokeStyle {
+    /// Create a stroke of colour and line width, with default options
+    pub fn new(colour: [f32; 4], width: f32) -> Self {
+        Self {
+            options: StrokeOptions::default().with_line_width(width),
+            colour,
+            anti_alias: true,
+        }
+    }
+
+    /// Set the stroke options
+    pub fn with_options(mut self, options: StrokeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set whether the stroke is anti-aliased
+    pub fn with_anti_alias(mut self, anti_alias: bool) -> Self {
+        self.anti_alias = anti_alias;
+        self
+    }
+}
+
+/// Anti-aliasing coordinate of a stroke vertex, with mode, e.g. AA_STROKE, for
+/// hand tessellated strokes:
+///
+/// ```ignore
+/// BuffersBuilder::new(&mut geometry, |pos: Point, attributes: StrokeAttributes| {
+///     VertexType {
+///         position: pos.to_array(),
+///         colour,
+///         aa: stroke_aa(&attributes, AA_STROKE),
+///     }
+/// })
+/// ```
+pub fn stroke_aa(attributes: &StrokeAttributes, mode: f32) -> [f32; 2] {
+    match attributes.side() {
+        Side::Left => [-1.0, mode],
+        Side::Right => [1.0, mode],
+    }
+}
+
+/// Options of the anti-aliasing fringe stroked around a fill with options, drawn at 
+/// pixels_per_unit physical pixels per mesh unit
+pub(crate) fn fringe_options(options: &FillOptions, pixels_per_unit: f32) -> StrokeOptions {
+    StrokeOptions::tolerance(options.tolerance).with_line_width(AA_FRINGE_WIDTH / pixels_per_unit)
+}
+
+/// Marks vertices as not painted by their mesh's gradient or texture, so that the stroke 
+/// of a shape with a gradient fill keeps its own colour
+pub(crate) fn unpainted(vertices: &mut [VertexType]) {
+    for vertex in vertices {
+        vertex.aa[1] += AA_UNPAINTED;
+    }
+}
+
+/// Fill path into geometry, with a fringe for drawing at one physical pixel per unit
+pub fn fill_path(path: &Path, style: &FillStyle, geometry: &mut Geometry) -> Result<(), TessellationError> {
+    fill_path_at_scale(path, style, 1.0, geometry)
+}
+
+/// Fill path into geometry, with a fringe for drawing at pixels_per_unit physical pixels per unit
+pub fn fill_path_at_scale(
+    path: &Path,
+    style: &FillStyle,
+    pixels_per_unit: f32,
+    geometry: &mut Geometry,
+) -> Result<(), TessellationError> {
+    let colour = style.colour;
+    FillTessellator::new().tessellate_path(
+        path,
+        &style.options,
+        &mut BuffersBuilder::new(geometry, |pos: Point, _: FillAttributes| {
+            VertexType {
+                position: pos.to_array(),
+                colour,
+                aa: [0.0, AA_NONE],
+            }
+        }),
+    )?;
+
+    if style.anti_alias {
+        StrokeTessellator::new().tessellate_path(
+            path,
+            &fringe_options(&style.options, pixels_per_unit),
+            &mut BuffersBuilder::new(geometry, |pos: Point, attributes: StrokeAttributes| {
+                VertexType {
+                    position: pos.to_array(),
+                    colour,
+                    aa: stroke_aa(&attributes, AA_FRINGE),
+                }
+            }),
+        )?;
+    }
+    Ok(())
+}
+
+/// Stroke path into geometry
+pub fn stroke_path(path: &Path, style: &StrokeStyle, geometry: &mut Geometry) -> Result<(), TessellationError> {
+    let colour = style.colour;
+    let mode = if style.anti_alias { AA_STROKE } else { AA_NONE };
+    StrokeTessellator::new().tessellate_path(
+        path,
+        &style.options,
+        &mut BuffersBuilder::new(geometry, |pos: Point, attributes: StrokeAttributes| {
+            VertexType {
+                position: pos.to_array(),
+                colour,
+                aa: stroke_aa(&attributes, mode),
+            }
+        }),
+    )?;
+    Ok(())
+}
+
+/// Component for a path to be tessellated into the entity's Mesh.
+///
+/// The path is filled and then stroked, so the stroke is drawn on top of the fill.
+/// Paths are stored in a flagged storage, and are re-tessellated whenever they
+/// are inserted or modified.
+#[derive(Clone, Debug)]
+pub struct LyonPath {
+    /// Path to be tessellated
+    pub path: Path,
+    /// Fill of the path, if any
+    pub fill: Option<FillStyle>,
+    /// Stroke of the path, if any
+    pub stroke: Option<StrokeStyle>,
+}
+
+impl LyonPath {
+    /// Create a path, with neither a fill or a stroke
+    pub fn new(path: Path) -> Self {
+        Self {
+            path,
+            fill: None,
+            stroke: None,
+        }
+    }
+
+    /// Set the fill of the path
+    pub fn with_fill(mut self, fill: FillStyle) -> Self {
+        self.fill = Some(fill);
+        self
+    }
+
+    /// Set the stroke of the path
+    pub fn with_stroke(mut self, stroke: StrokeStyle) -> Self {
+        self.stroke = Some(stroke);
+        self
+    }
+
+    /// Tessellate path into geometry, fill first and then stroke
+    pub fn tessellate(&self) -> Result<Geometry, TessellationError> {
+        self.tessellate_at_scale(1.0)
+    }
+
+    /// Tessellate path into geometry, for drawing at scale pixels per unit, dividing the 
+    /// tolerances of the fill and stroke, and the width of the fill's fringe, by scale
+    pub fn tessellate_at_scale(&self, scale: f32) -> Result<Geometry, TessellationError> {
+        let scale = if scale.is_finite() && scale > 0.0 { scale } else { 1.0 };
+        let mut geometry = Geometry::new();
+        if let Some(fill) = &self.fill {
+            let tolerance = fill.options.tolerance / scale;
+            let fill = fill.clone().with_options(fill.options.with_tolerance(tolerance));
+            fill_path_at_scale(&self.path, &fill, scale, &mut geometry)?;
+        }
+        if let Some(stroke) = &self.stroke {
+            let tolerance = stroke.options.tolerance / scale;
+            let stroke = stroke.with_options(stroke.options.with_tolerance(tolerance));
+            let start = geometry.vertices.len();
+            stroke_path(&self.path, &stroke, &mut geometry)?;
+            if self.fill.as_ref().is_some_and(|fill| fill.gradient.is_some()) {
+                unpainted(&mut geometry.vertices[start..]);
+            }
+        }
+        Ok(geometry)
+    }
+}
+
+impl Component for LyonPath {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+/// System that tessellates each inserted or modified LyonPath into its entity's Mesh.
+/// If the entity already has a Mesh, then its geometry and gradient are replaced, but its 
+/// scale is kept. All paths are re-tessellated when the screen's size or hidpi factor, or 
+/// the CoordinateSystem, changes.
+///
+/// Tolerances are divided by the physical pixels per unit of the path on screen, so a 
+/// tolerance of 0.1 is a tenth of a pixel. This is the pixels per unit of screen space, or 
+/// of the UiTransform for paths with one, as if placed in CoordinateMode::Ui, times the 
+/// scale of the path's global Transform and its Mesh. WorldSpace paths, whose size on 
+/// screen also depends on the camera, are only scaled by their Transform and Mesh. The 
+/// scale is rounded up to a power of two, so a path is re-tessellated when its scale has 
+/// doubled or halved, rather than every frame of a scaling animation.
+#[derive(Debug, Default)]
+pub struct TessellatePathSystem {
+    reader: Option<ReaderId<ComponentEvent>>,
+    /// screen size, hidpi factor and coordinate system paths were last tessellated for
+    screen: Option<(f64, f64, f64, CoordinateSystem)>,
+    /// scale each path was last tessellated at, by entity id
+    scales: HashMap<u32, f32>,
+}
+
+/// Largest scale of a transform's x and y axes
+fn transform_scale(transform: &Transform) -> f32 {
+    let matrix = transform.global_matrix();
+    let x = Vector2::new(matrix[(0, 0)], matrix[(1, 0)]).norm();
+    let y = Vector2::new(matrix[(0, 1)], matrix[(1, 1)]).norm();
+    x.max(y)
+}
+
+/// Physical pixels per unit of a UI element, as a mesh is placed by it in CoordinateMode::Ui
+fn ui_scale(ui: &UiTransform) -> f32 {
+    let ratio = |pixels: f32, size: f32| if size != 0.0 { (pixels / size).abs() } else { 1.0 };
+    ratio(ui.pixel_width(), ui.width).max(ratio(ui.pixel_height(), ui.height))
+}
+
+/// Rounds scale up to a power of two
+fn round_scale(scale: f32) -> f32 {
+    if scale.is_finite() && scale > 0.0 {
+        scale.log2().ceil().exp2()
+    } else {
+        1.0
+    }
+}
+
+impl<'a> System<'a> for TessellatePathSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, LyonPath>,
+        ReadStorage<'a, WorldSpace>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, UiTransform>,
+        WriteStorage<'a, Mesh>,
+        Option<Read<'a, ScreenDimensions>>,
+        Read<'a, CoordinateSystem>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, paths, world_space, transforms, ui_transforms, mut meshes, screen_dimensions, coordinates): Self::SystemData,
+    ) {
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("`TessellatePathSystem::setup` was not called before `TessellatePathSystem::run`");
+
+        let mut dirty = BitSet::new();
+        for event in paths.channel().read(reader) {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    dirty.add(*id);
+                }
+                ComponentEvent::Removed(id) => {
+                    self.scales.remove(id);
+                }
+            }
+        }
+
+        // physical pixels per unit of screen space, or 1 until the window is created
+        let pixels_per_unit = screen_dimensions.as_ref().map_or(1.0, |screen| {
+            let (width, height) = (screen.width(), screen.height());
+            let scale = coordinates.pixels_per_unit(width, height, screen.hidpi_factor() as f32);
+            scale.x.max(scale.y)
+        });
+
+        // the on-screen scale of every path changes with the screen
+        let screen = screen_dimensions.as_ref().map(|screen| {
+            (f64::from(screen.width()), f64::from(screen.height()), screen.hidpi_factor(), *coordinates)
+        });
+        if screen != self.screen {
+            for (entity, _) in (&entities, &paths).join() {
+                dirty.add(entity.id());
+            }
+            self.screen = screen;
+        }
+
+        // physical pixels per mesh unit of the path of entity
+        let scale_of = |entity: Entity| {
+            let mesh_scale = meshes.get(entity).map_or(1.0, |mesh| mesh.scale.x.abs().max(mesh.scale.y.abs()));
+            let placement = match ui_transforms.get(entity) {
+                Some(ui) => ui_scale(ui),
+                None => {
+                    let transform = transforms.get(entity).map_or(1.0, transform_scale);
+                    if world_space.contains(entity) { transform } else { pixels_per_unit * transform }
+                }
+            };
+            round_scale(placement * mesh_scale)
+        };
+
+        // paths are also re-tessellated when their scale changes, e.g. with their Transform
+        let scales = &self.scales;
+        let changed: Vec<_> = (&entities, &paths)
+            .join()
+            .map(|(entity, path)| (entity, path, scale_of(entity)))
+            .filter(|(entity, _, scale)| dirty.contains(entity.id()) || scales.get(&entity.id()) != Some(scale))
+            .collect();
+
+        for (entity, path, scale) in changed {
+            self.scales.insert(entity.id(), scale);
+            match path.tessellate_at_scale(scale) {
+                Ok(geometry) => {
+                    let gradient = path.fill.as_ref().and_then(|fill| fill.gradient.clone());
+                    if let Some(mesh) = meshes.get_mut(entity) {
+                        mesh.vertices = geometry.vertices;
+                        mesh.indices = geometry.indices;
+                        mesh.gradient = gradient;
+                    } else if let Err(e) = meshes.insert(entity, Mesh {
+                        vertices: geometry.vertices,
+                        indices: geometry.indices,
+                        gradient,
+                        ..Mesh::default()
+                    }) {
+                        log::error!("Failed to insert mesh for path of {:?}: {:?}", entity, e);
+                    }
+                }
+                Err(e) => log::error!("Failed to tessellate path of {:?}: {:?}", entity, e),
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(WriteStorage::<LyonPath>::fetch(world).register_reader());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lyon::math::point;
+
+    /// Filled 10 by 10 square, from the origin
+    fn square() -> LyonPath {
+        let mut builder = Path::builder();
+        builder.move_to(point(0.0, 0.0));
+        builder.line_to(point(10.0, 0.0));
+        builder.line_to(point(10.0, 10.0));
+        builder.line_to(point(0.0, 10.0));
+        builder.close();
+        LyonPath::new(builder.build()).with_fill(FillStyle::new([1.0; 4]))
+    }
+
+    /// How far the fringe reaches out from the square
+    fn fringe_reach(geometry: &Geometry) -> f32 {
+        let (min, max) = crate::utils::vertex_bounds(&geometry.vertices).unwrap();
+        assert!((min.x + max.x - 10.0).abs() < 1e-4);
+        -min.x
+    }
+
+    #[test]
+    fn fringe_is_sized_in_pixels() {
+        let half = AA_FRINGE_WIDTH / 2.0;
+        assert!((fringe_reach(&square().tessellate().unwrap()) - half).abs() < 1e-4);
+        assert!((fringe_reach(&square().tessellate_at_scale(4.0).unwrap()) - half / 4.0).abs() < 1e-4);
+        assert!((fringe_reach(&square().tessellate_at_scale(0.5).unwrap()) - half * 2.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,396 @@
+//! Description:
+//!
+//! Prefab support, so that Lyon shapes can be declared in RON files and
+//! loaded with the amethyst PrefabLoader.
+//!
+//! A ShapePrefab describes a shape, either a path or one of the common
+//! shapes from the shapes module, with an optional fill and stroke, and
+//! a scale. It is tessellated into a Mesh when the prefab is added to an
+//! entity, e.g.:
+//!
+//! ```ron
+//! #![enable(implicit_some)]
+//! Prefab(
+//!     entities: [
+//!         PrefabEntity(
+//!             data: ShapePrefab(
+//!                 shape: Star(center: (300.0, 300.0), outer_radius: 50.0, inner_radius: 20.0, points: 5),
+//!                 fill: (colour: (1.0, 1.0, 0.0, 1.0)),
+//!                 stroke: (colour: (0.0, 0.0, 0.0, 1.0), width: 4.0),
+//!             ),
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use amethyst::{
+    assets::PrefabData,
+    core::{
+        ecs::{Entity, WriteStorage},
+        math::Vector2,
+    },
+};
+use amethyst_error::Error;
+
+use lyon::{
+    math::{point, rect, vector, Angle, Point},
+    path::Path,
+    tessellation::{
+        basic_shapes::BorderRadii, FillOptions, FillRule, LineCap, LineJoin, StrokeOptions,
+        TessellationError,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gradient::Gradient;
+use crate::path::{fill_path, stroke_path, unpainted, FillStyle, Geometry, StrokeStyle};
+use crate::shapes;
+use crate::utils::Mesh;
+
+/// Command of a path, with points as (x, y)
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum PathCommand {
+    MoveTo([f32; 2]),
+    LineTo([f32; 2]),
+    QuadraticTo { ctrl: [f32; 2], to: [f32; 2] },
+    CubicTo { ctrl1: [f32; 2], ctrl2: [f32; 2], to: [f32; 2] },
+    Close,
+}
+
+/// Geometry of a shape, with points as (x, y) and angles in radians
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ShapeData {
+    Path(Vec<PathCommand>),
+    Rectangle { origin: [f32; 2], size: [f32; 2] },
+    RoundedRectangle { origin: [f32; 2], size: [f32; 2], radius: f32 },
+    Circle { center: [f32; 2], radius: f32 },
+    Ellipse { center: [f32; 2], radii: [f32; 2], #[serde(default)] rotation: f32 },
+    Polygon(Vec<[f32; 2]>),
+    RegularPolygon { center: [f32; 2], radius: f32, sides: u32 },
+    Star { center: [f32; 2], outer_radius: f32, inner_radius: f32, points: u32 },
+    Arrow { tail: [f32; 2], head: [f32; 2], shaft_width: f32, head_width: f32, head_length: f32 },
+}
+
+/// Fill of a shape, with an optional gradient multiplied with its colour
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct FillPrefab {
+    #[serde(default = "default_colour")]
+    pub colour: [f32; 4],
+    #[serde(default)]
+    pub gradient: Option<Gradient>,
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,
+    #[serde(default)]
+    pub even_odd: bool,
+    #[serde(default = "default_anti_alias")]
+    pub anti_alias: bool,
+}
+
+impl From<&FillPrefab> for FillStyle {
+    fn from(fill: &FillPrefab) -> Self {
+        let rule = if fill.even_odd { FillRule::EvenOdd } else { FillRule::NonZero };
+        FillStyle {
+            options: FillOptions::tolerance(fill.tolerance).with_fill_rule(rule),
+            colour: fill.colour,
+            gradient: fill.gradient.clone(),
+            anti_alias: fill.anti_alias,
+        }
+    }
+}
+
+/// Stroke of a shape
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StrokePrefab {
+    pub colour: [f32; 4],
+    pub width: f32,
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,
+    #[serde(default = "default_cap")]
+    pub cap: LineCap,
+    #[serde(default = "default_join")]
+    pub join: LineJoin,
+    #[serde(default = "default_anti_alias")]
+    pub anti_alias: bool,
+}
+
+impl From<&StrokePrefab> for StrokeStyle {
+    fn from(stroke: &StrokePrefab) -> Self {
+        StrokeStyle {
+            options: StrokeOptions::tolerance(stroke.tolerance)
+                .with_line_width(stroke.width)
+                .with_line_cap(stroke.cap)
+                .with_line_join(stroke.join),
+            colour: stroke.colour,
+            anti_alias: stroke.anti_alias,
+        }
+    }
+}
+
+fn default_colour() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_tolerance() -> f32 {
+    FillOptions::DEFAULT_TOLERANCE
+}
+
+fn default_cap() -> LineCap {
+    StrokeOptions::DEFAULT_LINE_CAP
+}
+
+fn default_join() -> LineJoin {
+    StrokeOptions::DEFAULT_LINE_JOIN
+}
+
+fn default_anti_alias() -> bool {
+    true
+}
+
+fn default_scale() -> [f32; 2] {
+    [1.0, 1.0]
+}
+
+/// Prefab of a shape, tessellated into a Mesh when added to an entity.
+/// The shape is filled and then stroked, so the stroke is drawn on top of the fill.
+/// A fill gradient paints only the fill, the stroke keeps its own colour.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ShapePrefab {
+    pub shape: ShapeData,
+    #[serde(default)]
+    pub fill: Option<FillPrefab>,
+    #[serde(default)]
+    pub stroke: Option<StrokePrefab>,
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 2],
+}
+
+impl ShapePrefab {
+    /// Tessellate shape into a mesh, failing with TessellationError::TooManyVertices if the 
+    /// fill and stroke together have too many vertices for IndexType
+    pub fn tessellate(&self) -> Result<Mesh, TessellationError> {
+        let mut mesh = Mesh::default();
+        if let Some(fill) = &self.fill {
+            mesh.append(self.tessellate_style(FillStyle::from(fill).into())?)?;
+            mesh.gradient = fill.gradient.clone();
+        }
+        if let Some(stroke) = &self.stroke {
+            let mut stroke = self.tessellate_style(StrokeStyle::from(stroke).into())?;
+            if mesh.gradient.is_some() {
+                unpainted(&mut stroke.vertices);
+            }
+            mesh.append(stroke)?;
+        }
+        mesh.scale = Vector2::new(self.scale[0], self.scale[1]);
+        Ok(mesh)
+    }
+
+    fn tessellate_style(&self, style: shapes::ShapeStyle) -> Result<Mesh, TessellationError> {
+        let to_point = |p: &[f32; 2]| point(p[0], p[1]);
+        match &self.shape {
+            ShapeData::Path(commands) => {
+                let path = to_lyon_path(commands);
+                let mut geometry = Geometry::new();
+                match style {
+                    shapes::ShapeStyle::Fill(fill) => fill_path(&path, &fill, &mut geometry)?,
+                    shapes::ShapeStyle::Stroke(stroke) => stroke_path(&path, &stroke, &mut geometry)?,
+                }
+                Ok(Mesh {
+                    vertices: geometry.vertices,
+                    indices: geometry.indices,
+                    ..Mesh::default()
+                })
+            }
+            ShapeData::Rectangle { origin, size } => {
+                shapes::rectangle(&rect(origin[0], origin[1], size[0], size[1]), style)
+            }
+            ShapeData::RoundedRectangle { origin, size, radius } => shapes::rounded_rectangle(
+                &rect(origin[0], origin[1], size[0], size[1]),
+                &BorderRadii::new_all_same(*radius),
+                style,
+            ),
+            ShapeData::Circle { center, radius } => shapes::circle(to_point(center), *radius, style),
+            ShapeData::Ellipse { center, radii, rotation } => shapes::ellipse(
+                to_point(center),
+                vector(radii[0], radii[1]),
+                Angle::radians(*rotation),
+                style,
+            ),
+            ShapeData::Polygon(points) => {
+                shapes::polygon(&points.iter().map(to_point).collect::<Vec<Point>>(), style)
+            }
+            ShapeData::RegularPolygon { center, radius, sides } => {
+                shapes::regular_polygon(to_point(center), *radius, *sides, style)
+            }
+            ShapeData::Star { center, outer_radius, inner_radius, points } => {
+                shapes::star(to_point(center), *outer_radius, *inner_radius, *points, style)
+            }
+            ShapeData::Arrow { tail, head, shaft_width, head_width, head_length } => shapes::arrow(
+                to_point(tail),
+                to_point(head),
+                *shaft_width,
+                *head_width,
+                *head_length,
+                style,
+            ),
+        }
+    }
+}
+
+impl<'a> PrefabData<'a> for ShapePrefab {
+    type SystemData = WriteStorage<'a, Mesh>;
+    type Result = ();
+
+    fn add_to_entity(
+        &self,
+        entity: Entity,
+        meshes: &mut Self::SystemData,
+        _entities: &[Entity],
+        _children: &[Entity],
+    ) -> Result<(), Error> {
+        let mesh = self
+            .tessellate()
+            .map_err(|e| Error::from_string(format!("Failed to tessellate shape prefab: {:?}", e)))?;
+        meshes.insert(entity, mesh).map(|_| ())?;
+        Ok(())
+    }
+}
+
+/// Convert path commands into a Lyon path
+fn to_lyon_path(commands: &[PathCommand]) -> Path {
+    let to_point = |p: &[f32; 2]| point(p[0], p[1]);
+
+    let mut builder = Path::builder();
+    for command in commands {
+        match command {
+            PathCommand::MoveTo(to) => {
+                builder.move_to(to_point(to));
+            }
+            PathCommand::LineTo(to) => {
+                builder.line_to(to_point(to));
+            }
+            PathCommand::QuadraticTo { ctrl, to } => {
+                builder.quadratic_bezier_to(to_point(ctrl), to_point(to));
+            }
+            PathCommand::CubicTo { ctrl1, ctrl2, to } => {
+                builder.cubic_bezier_to(to_point(ctrl1), to_point(ctrl2), to_point(to));
+            }
+            PathCommand::Close => builder.close(),
+        }
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::{VertexType, AA_NONE, AA_STROKE, AA_UNPAINTED};
+
+    #[test]
+    fn deserialize_with_defaults() {
+        let prefab: ShapePrefab = ron::de::from_str(
+            r#"
+            #![enable(implicit_some)]
+            ShapePrefab(
+                shape: Star(center: (300.0, 300.0), outer_radius: 50.0, inner_radius: 20.0, points: 5),
+                fill: (colour: (1.0, 1.0, 0.0, 1.0)),
+                stroke: (colour: (0.0, 0.0, 0.0, 1.0), width: 4.0),
+            )
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            prefab.shape,
+            ShapeData::Star { center: [300.0, 300.0], outer_radius: 50.0, inner_radius: 20.0, points: 5 }
+        );
+        assert_eq!(
+            prefab.fill,
+            Some(FillPrefab {
+                colour: [1.0, 1.0, 0.0, 1.0],
+                gradient: None,
+                tolerance: FillOptions::DEFAULT_TOLERANCE,
+                even_odd: false,
+                anti_alias: true,
+            })
+        );
+        assert_eq!(
+            prefab.stroke,
+            Some(StrokePrefab {
+                colour: [0.0, 0.0, 0.0, 1.0],
+                width: 4.0,
+                tolerance: FillOptions::DEFAULT_TOLERANCE,
+                cap: StrokeOptions::DEFAULT_LINE_CAP,
+                join: StrokeOptions::DEFAULT_LINE_JOIN,
+                anti_alias: true,
+            })
+        );
+        assert_eq!(prefab.scale, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn deserialize_path() {
+        let prefab: ShapePrefab = ron::de::from_str(
+            r#"
+            #![enable(implicit_some)]
+            ShapePrefab(
+                shape: Path([
+                    MoveTo((0.0, 0.0)),
+                    LineTo((10.0, 0.0)),
+                    QuadraticTo(ctrl: (10.0, 10.0), to: (0.0, 10.0)),
+                    Close,
+                ]),
+                fill: (even_odd: true, anti_alias: false),
+                stroke: (colour: (1.0, 0.0, 0.0, 1.0), width: 2.0, cap: Round, join: Bevel),
+                scale: (2.0, 3.0),
+            )
+            "#,
+        )
+        .unwrap();
+
+        let fill = prefab.fill.as_ref().unwrap();
+        assert_eq!(fill.colour, [1.0, 1.0, 1.0, 1.0]);
+        assert!(fill.even_odd);
+        assert!(!fill.anti_alias);
+        let stroke = prefab.stroke.as_ref().unwrap();
+        assert_eq!(stroke.cap, LineCap::Round);
+        assert_eq!(stroke.join, LineJoin::Bevel);
+
+        let mesh = prefab.tessellate().unwrap();
+        assert!(!mesh.indices.is_empty());
+        assert!(mesh.indices.iter().all(|&index| (index as usize) < mesh.vertices.len()));
+        assert_eq!(mesh.scale, Vector2::new(2.0, 3.0));
+    }
+
+    #[test]
+    fn gradient_fill_does_not_paint_stroke() {
+        let prefab = ShapePrefab {
+            shape: ShapeData::Circle { center: [0.0, 0.0], radius: 10.0 },
+            fill: Some(FillPrefab {
+                colour: default_colour(),
+                gradient: Some(Gradient::radial(point(0.0, 0.0), 10.0).with_stop(0.0, [1.0; 4])),
+                tolerance: default_tolerance(),
+                even_odd: false,
+                anti_alias: false,
+            }),
+            stroke: Some(StrokePrefab {
+                colour: [0.0, 0.0, 0.0, 1.0],
+                width: 2.0,
+                tolerance: default_tolerance(),
+                cap: default_cap(),
+                join: default_join(),
+                anti_alias: true,
+            }),
+            scale: default_scale(),
+        };
+        let mesh = prefab.tessellate().unwrap();
+        assert!(mesh.gradient.is_some());
+        let (fill, stroke): (Vec<&VertexType>, Vec<_>) = mesh.vertices.iter().partition(|vertex| vertex.colour[0] == 1.0);
+        assert!(!fill.is_empty() && !stroke.is_empty());
+        assert!(fill.iter().all(|vertex| vertex.aa[1] == AA_NONE));
+        assert!(stroke.iter().all(|vertex| vertex.aa[1] == AA_STROKE + AA_UNPAINTED));
+    }
+}
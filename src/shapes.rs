@@ -0,0 +1,405 @@
+//! Description:
+//!
+//! Builders for meshes of common shapes, e.g. rectangles, circles and stars.
+//!
+//! Each shape is either filled, with a single colour or a gradient, or stroked
+//! with a single colour, and is built on top of Lyon's basic shape tessellation
+//! functions. Ellipses, which Lyon can't fill directly, are tessellated as a path.
+//! The anti-aliasing fringes of fills are sized for drawing at one pixel per unit.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use lyon::{
+    geom::Arc,
+    math::{vector, Angle, Point, Rect, Vector},
+    path::Path,
+    tessellation::{
+        basic_shapes::{
+            fill_circle, fill_polyline, fill_rectangle, fill_rounded_rectangle, stroke_circle,
+            stroke_polyline, stroke_rectangle, stroke_rounded_rectangle, BorderRadii,
+        },
+        BasicVertexConstructor, BuffersBuilder, FillAttributes, FillOptions, FillTessellator,
+        FillVertexConstructor, StrokeAttributes, StrokeOptions, StrokeTessellator,
+        StrokeVertexConstructor, TessellationError, TessellationResult,
+    },
+};
+
+use crate::path::{fringe_options, stroke_aa, FillStyle, Geometry, StrokeStyle};
+use crate::utils::{IndexType, Mesh, VertexType, AA_FRINGE, AA_NONE, AA_STROKE};
+
+/// How a shape is drawn, either filled or stroked
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeStyle {
+    Fill(FillStyle),
+    Stroke(StrokeStyle),
+}
+
+impl From<FillStyle> for ShapeStyle {
+    fn from(style: FillStyle) -> Self {
+        ShapeStyle::Fill(style)
+    }
+}
+
+impl From<StrokeStyle> for ShapeStyle {
+    fn from(style: StrokeStyle) -> Self {
+        ShapeStyle::Stroke(style)
+    }
+}
+
+/// Vertex constructor, giving every vertex the same colour, and stroke vertices
+/// the anti-aliasing mode
+struct WithColour([f32; 4], f32);
+
+/// Used by the basic shape fills, e.g. of rectangles and circles
+impl BasicVertexConstructor<VertexType> for WithColour {
+    fn new_vertex(&mut self, position: Point) -> VertexType {
+        VertexType {
+            position: position.to_array(),
+            colour: self.0,
+            aa: [0.0, AA_NONE],
+        }
+    }
+}
+
+impl FillVertexConstructor<VertexType> for WithColour {
+    fn new_vertex(&mut self, position: Point, _: FillAttributes) -> VertexType {
+        VertexType {
+            position: position.to_array(),
+            colour: self.0,
+            aa: [0.0, AA_NONE],
+        }
+    }
+}
+
+impl StrokeVertexConstructor<VertexType> for WithColour {
+    fn new_vertex(&mut self, position: Point, attributes: StrokeAttributes) -> VertexType {
+        VertexType {
+            position: position.to_array(),
+            colour: self.0,
+            aa: stroke_aa(&attributes, self.1),
+        }
+    }
+}
+
+type Builder<'l> = BuffersBuilder<'l, VertexType, IndexType, WithColour>;
+
+/// Tessellate a shape into a mesh, with fill or stroke depending on style
+fn tessellate<F, S>(style: ShapeStyle, fill: F, stroke: S) -> Result<Mesh, TessellationError>
+where
+    F: FnOnce(&FillOptions, &mut Builder<'_>) -> TessellationResult,
+    S: FnOnce(&StrokeOptions, &mut Builder<'_>) -> TessellationResult,
+{
+    let mut geometry = Geometry::new();
+    let gradient = match style {
+        ShapeStyle::Fill(style) => {
+            fill(&style.options, &mut BuffersBuilder::new(&mut geometry, WithColour(style.colour, AA_NONE)))?;
+            if style.anti_alias {
+                // the fringe is the outline of the shape, stroked
+                stroke(
+                    &fringe_options(&style.options, 1.0),
+                    &mut BuffersBuilder::new(&mut geometry, WithColour(style.colour, AA_FRINGE)),
+                )?;
+            }
+            style.gradient
+        }
+        ShapeStyle::Stroke(style) => {
+            let mode = if style.anti_alias { AA_STROKE } else { AA_NONE };
+            stroke(&style.options, &mut BuffersBuilder::new(&mut geometry, WithColour(style.colour, mode)))?;
+            None
+        }
+    };
+
+    Ok(Mesh {
+        vertices: geometry.vertices,
+        indices: geometry.indices,
+        gradient,
+        ..Mesh::default()
+    })
+}
+
+/// Mesh of an axis aligned rectangle
+pub fn rectangle<S: Into<ShapeStyle>>(rect: &Rect, style: S) -> Result<Mesh, TessellationError> {
+    tessellate(
+        style.into(),
+        |options, output| fill_rectangle(rect, options, output),
+        |options, output| stroke_rectangle(rect, options, output),
+    )
+}
+
+/// Mesh of an axis aligned rectangle, with rounded corners
+pub fn rounded_rectangle<S: Into<ShapeStyle>>(
+    rect: &Rect,
+    radii: &BorderRadii,
+    style: S,
+) -> Result<Mesh, TessellationError> {
+    tessellate(
+        style.into(),
+        |options, output| fill_rounded_rectangle(rect, radii, options, output),
+        |options, output| stroke_rounded_rectangle(rect, radii, options, output),
+    )
+}
+
+/// Mesh of a circle
+pub fn circle<S: Into<ShapeStyle>>(center: Point, radius: f32, style: S) -> Result<Mesh, TessellationError> {
+    tessellate(
+        style.into(),
+        |options, output| fill_circle(center, radius, options, output),
+        |options, output| stroke_circle(center, radius, options, output),
+    )
+}
+
+/// Mesh of an ellipse, with radii along its x and y axes, rotated by x_rotation
+pub fn ellipse<S: Into<ShapeStyle>>(
+    center: Point,
+    radii: Vector,
+    x_rotation: Angle,
+    style: S,
+) -> Result<Mesh, TessellationError> {
+    let arc = Arc {
+        center,
+        radii,
+        x_rotation,
+        start_angle: Angle::radians(0.0),
+        sweep_angle: Angle::two_pi(),
+    };
+    let mut builder = Path::builder();
+    builder.move_to(arc.from());
+    arc.for_each_quadratic_bezier(&mut |curve| {
+        builder.quadratic_bezier_to(curve.ctrl, curve.to);
+    });
+    builder.close();
+    let path = builder.build();
+
+    tessellate(
+        style.into(),
+        |options, output| FillTessellator::new().tessellate_path(&path, options, output),
+        |options, output| StrokeTessellator::new().tessellate_path(&path, options, output),
+    )
+}
+
+/// Mesh of a closed polygon, which need not be convex
+pub fn polygon<S: Into<ShapeStyle>>(points: &[Point], style: S) -> Result<Mesh, TessellationError> {
+    tessellate(
+        style.into(),
+        |options, output| {
+            fill_polyline(points.iter().copied(), &mut FillTessellator::new(), options, output)
+        },
+        |options, output| stroke_polyline(points.iter().copied(), true, options, output),
+    )
+}
+
+/// Mesh of a regular polygon with sides (at least 3), whose corners lie on a circle of radius.
+/// The first corner points up.
+pub fn regular_polygon<S: Into<ShapeStyle>>(
+    center: Point,
+    radius: f32,
+    sides: u32,
+    style: S,
+) -> Result<Mesh, TessellationError> {
+    let sides = sides.max(3);
+    let points = (0..sides)
+        .map(|i| center + corner(radius, i as f32 / sides as f32))
+        .collect::<Vec<Point>>();
+    polygon(&points, style)
+}
+
+/// Mesh of a star with points (at least 2), alternating between outer and inner radius.
+/// The first point points up.
+pub fn star<S: Into<ShapeStyle>>(
+    center: Point,
+    outer_radius: f32,
+    inner_radius: f32,
+    points: u32,
+    style: S,
+) -> Result<Mesh, TessellationError> {
+    let corners = points.max(2) * 2;
+    let points = (0..corners)
+        .map(|i| {
+            let radius = if i % 2 == 0 { outer_radius } else { inner_radius };
+            center + corner(radius, i as f32 / corners as f32)
+        })
+        .collect::<Vec<Point>>();
+    polygon(&points, style)
+}
+
+/// Mesh of an arrow from tail to head, with a shaft of shaft_width and a
+/// triangular head of head_width and head_length
+pub fn arrow<S: Into<ShapeStyle>>(
+    tail: Point,
+    head: Point,
+    shaft_width: f32,
+    head_width: f32,
+    head_length: f32,
+    style: S,
+) -> Result<Mesh, TessellationError> {
+    let length = (head - tail).length();
+    let direction = if length > 0.0 { (head - tail) / length } else { vector(1.0, 0.0) };
+    let normal = vector(-direction.y, direction.x);
+
+    // the head never extends past the tail
+    let neck = tail + direction * (length - head_length.min(length));
+    let shaft = normal * (shaft_width * 0.5);
+    let barb = normal * (head_width * 0.5);
+
+    polygon(
+        &[
+            tail + shaft,
+            neck + shaft,
+            neck + barb,
+            head,
+            neck - barb,
+            neck - shaft,
+            tail - shaft,
+        ],
+        style,
+    )
+}
+
+/// Offset of the corner a fraction of a turn around a circle of radius, starting from up
+fn corner(radius: f32, turn: f32) -> Vector {
+    let angle = std::f32::consts::FRAC_PI_2 + turn * 2.0 * std::f32::consts::PI;
+    vector(radius * angle.cos(), radius * angle.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lyon::math::{point, rect};
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+    /// Aliased fill, so that the mesh is only the shape's triangles
+    fn fill() -> FillStyle {
+        FillStyle::new(RED).with_anti_alias(false)
+    }
+
+    /// Aliased stroke of width 2
+    fn stroke() -> StrokeStyle {
+        StrokeStyle::new(RED, 2.0).with_anti_alias(false)
+    }
+
+    fn assert_counts(mesh: &Mesh, vertices: usize, indices: usize) {
+        assert_eq!((mesh.vertices.len(), mesh.indices.len()), (vertices, indices));
+    }
+
+    /// Bounds to within 0.01, as the joins of a flattened stroke can overshoot slightly
+    fn assert_bounds(mesh: &Mesh, min: [f32; 2], max: [f32; 2]) {
+        let (low, high) = mesh.bounds().unwrap();
+        for (actual, expected) in [low.x, low.y, high.x, high.y].iter().zip(&[min[0], min[1], max[0], max[1]]) {
+            assert!((actual - expected).abs() < 1e-2, "bounds {:?} {:?}, expected {:?} {:?}", low, high, min, max);
+        }
+    }
+
+    /// Every index refers to a vertex, in whole triangles
+    fn assert_triangles(mesh: &Mesh) {
+        assert!(!mesh.indices.is_empty());
+        assert_eq!(mesh.indices.len() % 3, 0);
+        assert!(mesh.indices.iter().all(|&index| (index as usize) < mesh.vertices.len()));
+    }
+
+    #[test]
+    fn rectangle_fill_and_stroke() {
+        let rect = rect(10.0, 20.0, 100.0, 50.0);
+        let mesh = rectangle(&rect, fill()).unwrap();
+        assert_counts(&mesh, 4, 6);
+        assert_bounds(&mesh, [10.0, 20.0], [110.0, 70.0]);
+
+        let mesh = rectangle(&rect, stroke()).unwrap();
+        assert_triangles(&mesh);
+        assert_bounds(&mesh, [9.0, 19.0], [111.0, 71.0]);
+    }
+
+    #[test]
+    fn rounded_rectangle_fill_and_stroke() {
+        let rect = rect(0.0, 0.0, 100.0, 50.0);
+        let radii = BorderRadii::new_all_same(10.0);
+        let mesh = rounded_rectangle(&rect, &radii, fill()).unwrap();
+        assert_triangles(&mesh);
+        assert!(mesh.vertices.len() > 8);
+        assert_bounds(&mesh, [0.0, 0.0], [100.0, 50.0]);
+
+        let mesh = rounded_rectangle(&rect, &radii, stroke()).unwrap();
+        assert_triangles(&mesh);
+        assert_bounds(&mesh, [-1.0, -1.0], [101.0, 51.0]);
+    }
+
+    #[test]
+    fn circle_fill_and_stroke() {
+        let mesh = circle(point(50.0, 50.0), 20.0, fill()).unwrap();
+        assert_triangles(&mesh);
+        assert!(mesh.vertices.len() > 4);
+        assert_bounds(&mesh, [30.0, 30.0], [70.0, 70.0]);
+
+        let mesh = circle(point(50.0, 50.0), 20.0, stroke()).unwrap();
+        assert_triangles(&mesh);
+        assert_bounds(&mesh, [29.0, 29.0], [71.0, 71.0]);
+    }
+
+    #[test]
+    fn ellipse_fill_and_stroke() {
+        let mesh = ellipse(point(0.0, 0.0), vector(40.0, 20.0), Angle::radians(0.0), fill()).unwrap();
+        assert_triangles(&mesh);
+        assert!(mesh.vertices.len() > 8);
+        assert_bounds(&mesh, [-40.0, -20.0], [40.0, 20.0]);
+
+        let mesh = ellipse(point(0.0, 0.0), vector(40.0, 20.0), Angle::radians(0.0), stroke()).unwrap();
+        assert_triangles(&mesh);
+        assert_bounds(&mesh, [-41.0, -21.0], [41.0, 21.0]);
+
+        // a quarter turn swaps the extents of the axes
+        let mesh = ellipse(point(0.0, 0.0), vector(40.0, 20.0), Angle::degrees(90.0), fill()).unwrap();
+        assert_bounds(&mesh, [-20.0, -40.0], [20.0, 40.0]);
+    }
+
+    #[test]
+    fn polygons() {
+        let points = [point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0), point(5.0, 5.0), point(0.0, 10.0)];
+        let mesh = polygon(&points, fill()).unwrap();
+        assert_counts(&mesh, 5, 9);
+        assert_bounds(&mesh, [0.0, 0.0], [10.0, 10.0]);
+
+        let mesh = polygon(&points, stroke()).unwrap();
+        assert_triangles(&mesh);
+
+        let mesh = regular_polygon(point(0.0, 0.0), 10.0, 4, fill()).unwrap();
+        assert_counts(&mesh, 4, 6);
+        assert_bounds(&mesh, [-10.0, -10.0], [10.0, 10.0]);
+
+        // fewer than 3 sides is a triangle
+        let mesh = regular_polygon(point(0.0, 0.0), 10.0, 1, fill()).unwrap();
+        assert_counts(&mesh, 3, 3);
+    }
+
+    #[test]
+    fn star_points() {
+        let mesh = star(point(0.0, 0.0), 10.0, 5.0, 5, fill()).unwrap();
+        assert_counts(&mesh, 10, 24);
+        // the first point is up
+        let (_, high) = mesh.bounds().unwrap();
+        assert!((high.y - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn arrow_shape() {
+        let mesh = arrow(point(0.0, 0.0), point(100.0, 0.0), 10.0, 30.0, 20.0, fill()).unwrap();
+        assert_counts(&mesh, 7, 15);
+        assert_bounds(&mesh, [0.0, -15.0], [100.0, 15.0]);
+
+        // the head is no longer than the arrow
+        let mesh = arrow(point(0.0, 0.0), point(10.0, 0.0), 10.0, 30.0, 20.0, fill()).unwrap();
+        assert_bounds(&mesh, [0.0, -15.0], [10.0, 15.0]);
+    }
+
+    #[test]
+    fn anti_aliased_fill_adds_fringe() {
+        let rect = rect(0.0, 0.0, 10.0, 10.0);
+        let mesh = rectangle(&rect, FillStyle::new(RED)).unwrap();
+        assert_triangles(&mesh);
+        assert_eq!(mesh.vertices.iter().filter(|v| v.aa[1] == AA_NONE).count(), 4);
+        assert!(mesh.vertices.iter().skip(4).all(|v| v.aa[1] == AA_FRINGE));
+
+        let mesh = rectangle(&rect, StrokeStyle::new(RED, 2.0)).unwrap();
+        assert!(mesh.vertices.iter().all(|v| v.aa[1] == AA_STROKE));
+    }
+}
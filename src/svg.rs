@@ -0,0 +1,368 @@
+//! Description:
+//!
+//! Amethyst asset Format for loading SVG files, e.g. authored in Inkscape,
+//! into a LyonMesh.
+//!
+//! The SVG is parsed, and simplified, with usvg, and then each visible path
+//! is filled and stroked with its colour and opacity. SVG features that are
+//! not supported are reported as warnings, and ignored. usvg is built without
+//! text support, and drops text silently, so the SVG's text elements are found
+//! in its XML source and reported too.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use amethyst::assets::Format;
+use amethyst_error::Error;
+
+use lyon::{
+    math::point,
+    path::Path,
+    tessellation::{FillOptions, FillRule, LineCap, LineJoin, StrokeOptions},
+};
+
+use usvg::{NodeExt, NodeKind, PathSegment, Transform};
+
+use crate::assets::LyonMesh;
+use crate::path::{fill_path, stroke_path, FillStyle, Geometry, StrokeStyle};
+
+/// Format for SVG files, loaded as a LyonMesh:
+///
+/// ```ignore
+/// let handle = loader.load("logo.svg", SvgFormat::default(), (), &world.read_resource());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SvgFormat {
+    /// Tessellation tolerance, in SVG units
+    pub tolerance: f32,
+    /// Flip the y axis, so that the SVG, whose origin is top-left, appears
+    /// upright in screen space, whose origin is bottom-left by default. Disable 
+    /// it if the CoordinateSystem's origin is top-left.
+    pub flip_y: bool,
+}
+
+impl Default for SvgFormat {
+    fn default() -> Self {
+        Self {
+            tolerance: FillOptions::DEFAULT_TOLERANCE,
+            flip_y: true,
+        }
+    }
+}
+
+impl Format<LyonMesh> for SvgFormat {
+    fn name(&self) -> &'static str {
+        "SVG"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<LyonMesh, Error> {
+        let tree = usvg::Tree::from_data(&bytes, &usvg::Options::default())
+            .map_err(|e| Error::from_string(format!("Failed to parse SVG: {}", e)))?;
+
+        warn_text(&bytes);
+
+        let view_box = tree.svg_node().view_box.rect;
+        let mut geometry = Geometry::new();
+
+        for node in tree.root().descendants() {
+            match *node.borrow() {
+                NodeKind::Path(ref path) => {
+                    // the path's own transform applies after those of its ancestors
+                    let mut transform = node.abs_transform();
+                    transform.append(&path.transform);
+                    if self.flip_y {
+                        let mut flip = Transform::new(1.0, 0.0, 0.0, -1.0, 0.0, 2.0 * view_box.y() + view_box.height());
+                        flip.append(&transform);
+                        transform = flip;
+                    }
+                    self.tessellate_path(path, &transform, &mut geometry)?;
+                }
+                NodeKind::Group(ref group) => {
+                    if group.clip_path.is_some() {
+                        log::warn!("SVG clip paths are not supported, ignoring clip path of group {:?}", group.id);
+                    }
+                    if group.mask.is_some() {
+                        log::warn!("SVG masks are not supported, ignoring mask of group {:?}", group.id);
+                    }
+                    if group.filter.is_some() {
+                        log::warn!("SVG filters are not supported, ignoring filter of group {:?}", group.id);
+                    }
+                    if group.opacity.value() < 1.0 {
+                        log::warn!("SVG group opacity is not supported, ignoring opacity of group {:?}", group.id);
+                    }
+                }
+                NodeKind::Image(ref image) => {
+                    log::warn!("SVG images are not supported, ignoring image {:?}", image.id);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(LyonMesh {
+            vertices: geometry.vertices,
+            indices: geometry.indices,
+            ..LyonMesh::default()
+        })
+    }
+}
+
+impl SvgFormat {
+    /// Fill and stroke an SVG path, transformed into mesh coords, into geometry
+    fn tessellate_path(&self, path: &usvg::Path, transform: &Transform, geometry: &mut Geometry) -> Result<(), Error> {
+        if path.visibility != usvg::Visibility::Visible {
+            return Ok(());
+        }
+
+        let lyon_path = to_lyon_path(&path.data.0, transform);
+
+        if let Some(fill) = &path.fill {
+            if let Some(colour) = colour(&fill.paint, fill.opacity.value(), &path.id) {
+                let rule = match fill.rule {
+                    usvg::FillRule::NonZero => FillRule::NonZero,
+                    usvg::FillRule::EvenOdd => FillRule::EvenOdd,
+                };
+                let style = FillStyle::new(colour).with_options(
+                    FillOptions::tolerance(self.tolerance).with_fill_rule(rule),
+                );
+                fill_path(&lyon_path, &style, geometry)
+                    .map_err(|e| Error::from_string(format!("Failed to fill SVG path {:?}: {:?}", path.id, e)))?;
+            }
+        }
+
+        if let Some(stroke) = &path.stroke {
+            if stroke.dasharray.is_some() {
+                log::warn!("SVG dashed strokes are not supported, drawing path {:?} with a solid stroke", path.id);
+            }
+            if let Some(colour) = colour(&stroke.paint, stroke.opacity.value(), &path.id) {
+                // the stroke is tessellated after transforming the path, so its width must be scaled too
+                let scale = (transform.a * transform.d - transform.b * transform.c).abs().sqrt();
+                let cap = match stroke.linecap {
+                    usvg::LineCap::Butt => LineCap::Butt,
+                    usvg::LineCap::Round => LineCap::Round,
+                    usvg::LineCap::Square => LineCap::Square,
+                };
+                let join = match stroke.linejoin {
+                    usvg::LineJoin::Miter => LineJoin::Miter,
+                    usvg::LineJoin::Round => LineJoin::Round,
+                    usvg::LineJoin::Bevel => LineJoin::Bevel,
+                };
+                let style = StrokeStyle {
+                    options: StrokeOptions::tolerance(self.tolerance)
+                        .with_line_width((stroke.width.value() * scale) as f32)
+                        .with_line_cap(cap)
+                        .with_line_join(join)
+                        .with_miter_limit(stroke.miterlimit.value() as f32),
+                    colour,
+                    anti_alias: true,
+                };
+                stroke_path(&lyon_path, &style, geometry)
+                    .map_err(|e| Error::from_string(format!("Failed to stroke SVG path {:?}: {:?}", path.id, e)))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Namespace of SVG elements
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+
+/// Warn about each text element of the SVG source, which usvg ignores. Compressed SVGs,
+/// which usvg inflates itself, are not searched.
+fn warn_text(bytes: &[u8]) {
+    let document = match std::str::from_utf8(bytes).ok().and_then(|text| roxmltree::Document::parse(text).ok()) {
+        Some(document) => document,
+        None => return,
+    };
+    for node in document.descendants().filter(|node| node.has_tag_name((SVG_NS, "text"))) {
+        log::warn!("SVG text is not supported, ignoring text {:?}", node.attribute("id").unwrap_or_default());
+    }
+}
+
+/// Colour of a paint, or None with a warning if the paint is not a plain colour
+fn colour(paint: &usvg::Paint, opacity: f64, id: &str) -> Option<[f32; 4]> {
+    match paint {
+        usvg::Paint::Color(c) => Some([
+            f32::from(c.red) / 255.0,
+            f32::from(c.green) / 255.0,
+            f32::from(c.blue) / 255.0,
+            opacity as f32,
+        ]),
+        usvg::Paint::Link(server) => {
+            log::warn!("SVG gradients and patterns are not supported, ignoring paint {:?} of path {:?}", server, id);
+            None
+        }
+    }
+}
+
+/// Convert the segments of an SVG path into a Lyon path, applying transform to each point
+fn to_lyon_path(segments: &[PathSegment], transform: &Transform) -> Path {
+    let to_point = |x: f64, y: f64| {
+        let (x, y) = transform.apply(x, y);
+        point(x as f32, y as f32)
+    };
+
+    let mut builder = Path::builder();
+    for segment in segments {
+        match *segment {
+            PathSegment::MoveTo { x, y } => {
+                builder.move_to(to_point(x, y));
+            }
+            PathSegment::LineTo { x, y } => {
+                builder.line_to(to_point(x, y));
+            }
+            PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                builder.cubic_bezier_to(to_point(x1, y1), to_point(x2, y2), to_point(x, y));
+            }
+            PathSegment::ClosePath => builder.close(),
+        }
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Mutex, Once};
+
+    /// Logger recording the messages of warnings, shared by the tests
+    struct Warnings(Mutex<Vec<String>>);
+
+    impl log::Log for Warnings {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    lazy_static::lazy_static! {
+        static ref WARNINGS: Warnings = Warnings(Mutex::new(Vec::new()));
+    }
+
+    /// Record warnings from now on, the logger can only be set once per test binary
+    fn record_warnings() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&*WARNINGS).unwrap();
+            log::set_max_level(log::LevelFilter::Warn);
+        });
+    }
+
+    /// Whether a warning containing message has been logged
+    fn warned(message: &str) -> bool {
+        WARNINGS.0.lock().unwrap().iter().any(|warning| warning.contains(message))
+    }
+
+    fn import(format: SvgFormat, svg: &str) -> LyonMesh {
+        record_warnings();
+        format.import_simple(svg.as_bytes().to_vec()).unwrap()
+    }
+
+    fn svg(body: &str) -> String {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100" viewBox="0 0 100 100">{}</svg>"#,
+            body
+        )
+    }
+
+    /// Centre of the mesh's bounds
+    fn centre(mesh: &LyonMesh) -> (f32, f32) {
+        let (min, max) = crate::utils::vertex_bounds(&mesh.vertices).unwrap();
+        ((min.x + max.x) / 2.0, (min.y + max.y) / 2.0)
+    }
+
+    #[test]
+    fn flip_y_puts_the_top_of_the_svg_at_the_top() {
+        let top_bar = svg(r#"<path d="M 10 0 H 90 V 10 H 10 Z" fill="red"/>"#);
+
+        let (x, y) = centre(&import(SvgFormat::default(), &top_bar));
+        assert!((x - 50.0).abs() < 1e-3);
+        assert!((y - 95.0).abs() < 1e-3, "flipped centre {}", y);
+
+        let unflipped = SvgFormat { flip_y: false, ..SvgFormat::default() };
+        let (_, y) = centre(&import(unflipped, &top_bar));
+        assert!((y - 5.0).abs() < 1e-3, "unflipped centre {}", y);
+    }
+
+    #[test]
+    fn flipped_svgs_are_upright_on_screen() {
+        use crate::utils::{CoordinateSystem, Origin, Units};
+        use amethyst::core::math::Vector4;
+
+        // the top of the SVG is drawn at the top of the screen, where Vulkan's y is -1, for 
+        // a bottom-left origin with flip_y, and for a top-left origin without it
+        let top_bar = svg(r#"<path d="M 10 0 H 90 V 10 H 10 Z" fill="red"/>"#);
+        let screen_y = |format: SvgFormat, origin: Origin| {
+            let (x, y) = centre(&import(format, &top_bar));
+            let projection = CoordinateSystem::new(origin, Units::Physical).projection(100.0, 100.0, 1.0);
+            (projection * Vector4::new(x, y, 0.0, 1.0)).y
+        };
+        let unflipped = SvgFormat { flip_y: false, ..SvgFormat::default() };
+        assert!((screen_y(SvgFormat::default(), Origin::BottomLeft) + 0.9).abs() < 1e-3);
+        assert!((screen_y(unflipped, Origin::TopLeft) + 0.9).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fills_and_strokes_paths() {
+        let mesh = import(
+            SvgFormat::default(),
+            &svg(r#"<path d="M 10 10 H 90 V 40 H 10 Z" fill="red" stroke="blue" stroke-width="4"/>"#),
+        );
+
+        // the same rectangle, flipped, filled and stroked with anti-aliasing
+        let mut builder = Path::builder();
+        builder.move_to(point(10.0, 90.0));
+        builder.line_to(point(90.0, 90.0));
+        builder.line_to(point(90.0, 60.0));
+        builder.line_to(point(10.0, 60.0));
+        builder.close();
+        let path = builder.build();
+        let (mut fill, mut stroke) = (Geometry::new(), Geometry::new());
+        fill_path(&path, &FillStyle::new([1.0, 0.0, 0.0, 1.0]), &mut fill).unwrap();
+        stroke_path(&path, &StrokeStyle::new([0.0, 0.0, 1.0, 1.0], 4.0), &mut stroke).unwrap();
+
+        let red = mesh.vertices.iter().filter(|v| v.colour == [1.0, 0.0, 0.0, 1.0]).count();
+        let blue = mesh.vertices.iter().filter(|v| v.colour == [0.0, 0.0, 1.0, 1.0]).count();
+        assert_eq!(red, fill.vertices.len());
+        assert_eq!(blue, stroke.vertices.len());
+        assert_eq!(mesh.vertices.len(), red + blue);
+        assert_eq!(mesh.indices.len(), fill.indices.len() + stroke.indices.len());
+
+        let (min, max) = crate::utils::vertex_bounds(&mesh.vertices).unwrap();
+        assert!((min.x - 8.0).abs() < 1e-3 && (max.x - 92.0).abs() < 1e-3);
+        assert!((min.y - 58.0).abs() < 1e-3 && (max.y - 92.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn unsupported_features_warn() {
+        let mesh = import(
+            SvgFormat::default(),
+            &svg(
+                r#"<defs><linearGradient id="fade"><stop offset="0" stop-color="red"/><stop offset="1" stop-color="blue"/></linearGradient></defs>
+                <path id="graded" d="M 0 0 H 10 V 10 Z" fill="url(#fade)"/>
+                <path id="dashed" d="M 0 50 H 100" stroke="black" stroke-dasharray="5 5"/>
+                <text id="caption" x="10" y="90">Hello</text>"#,
+            ),
+        );
+
+        // the gradient fill is ignored, and the dashed stroke drawn solid
+        assert!(!mesh.vertices.is_empty());
+        assert!(mesh.vertices.iter().all(|v| v.colour == [0.0, 0.0, 0.0, 1.0]));
+
+        assert!(warned("gradients and patterns are not supported"));
+        assert!(warned("dashed strokes are not supported"));
+        assert!(warned("text is not supported, ignoring text \"caption\""));
+    }
+
+    #[test]
+    fn invalid_svg_fails() {
+        assert!(SvgFormat::default().import_simple(b"not an svg".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! Description:
+//!
+//! Texture fills, so that meshes can be filled with an amethyst Texture,
+//! e.g. an image pattern.
+//!
+//! Texture coordinates are generated from the position of each pixel, in mesh
+//! coordinates (i.e. before the mesh's scale and transform), and the texture
+//! is multiplied with the colour of each vertex, except those marked
+//! utils::AA_UNPAINTED. A texture fill takes the place of a mesh's gradient.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use amethyst::{
+    assets::Handle,
+    core::ecs::{Component, DenseVecStorage},
+    renderer::Texture,
+};
+
+/// How a texture covers a mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureMode {
+    /// The texture is tiled across the mesh
+    Repeat,
+    /// The texture is drawn once, and its edge pixels extend outside of it
+    Clamp,
+    /// The texture is stretched to the bounds of the mesh
+    Stretch,
+}
+
+/// Component filling the entity's Mesh with a texture.
+///
+/// For Repeat and Clamp the texture's bottom-left corner is placed at origin, and it
+/// covers size, in mesh coordinates. For Stretch origin and size are ignored, and the
+/// texture covers the bounds of the mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureFill {
+    /// Texture to fill the mesh with
+    pub texture: Handle<Texture>,
+    /// How the texture covers the mesh
+    pub mode: TextureMode,
+    /// Position of the texture's bottom-left corner
+    pub origin: [f32; 2],
+    /// Size of the texture
+    pub size: [f32; 2],
+}
+
+impl TextureFill {
+    /// Fill with texture tiled from origin, with each tile of size
+    pub fn repeat(texture: Handle<Texture>, origin: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            texture,
+            mode: TextureMode::Repeat,
+            origin,
+            size,
+        }
+    }
+
+    /// Fill with texture drawn once at origin, with size
+    pub fn clamp(texture: Handle<Texture>, origin: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            texture,
+            mode: TextureMode::Clamp,
+            origin,
+            size,
+        }
+    }
+
+    /// Fill with texture stretched to the bounds of the mesh
+    pub fn stretch(texture: Handle<Texture>) -> Self {
+        Self {
+            texture,
+            mode: TextureMode::Stretch,
+            origin: [0.0, 0.0],
+            size: [1.0, 1.0],
+        }
+    }
+}
+
+impl Component for TextureFill {
+    type Storage = DenseVecStorage<Self>;
+}
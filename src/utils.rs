@@ -4,10 +4,13 @@
 //! 
 //! Copyright © 2020 Benedict Gaster. All rights reserved.
 //! 
+// the AsStd140 derive implements its std140 types within a const block
+#![allow(non_local_definitions)]
+
 use amethyst::{
-    core::ecs::{Component, DenseVecStorage, Entity},
+    core::ecs::{Component, DenseVecStorage, Entity, FlaggedStorage, NullStorage},
     core::{
-		math::{Vector2, Vector4},
+		math::{Matrix4, Vector2, Vector3, Vector4},
 	},
     renderer::{
         rendy::{
@@ -19,11 +22,21 @@ use amethyst::{
 
 use glsl_layout::*;
 
+use lyon::tessellation::TessellationError;
+
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashSet, convert::TryFrom};
+
+use crate::gradient::Gradient;
+use crate::texture::TextureMode;
+
 /// Vertex Arguments to pass into shader.
 /// VertexData in shader:
 /// layout(location = 0) out VertexData {
 ///    vec2 pos;
 ///    vec4 color;
+///    vec2 aa;
 /// } vertex;
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsStd140)]
 #[repr(C, align(4))]
@@ -32,6 +45,8 @@ pub struct CustomArgs {
     pub pos: vec2,
     /// vec4 color;
     pub color: vec4,
+    /// vec2 aa;
+    pub aa: vec2,
 }
 
 /// Required to send data into the shader.
@@ -43,61 +58,174 @@ impl AsVertex for CustomArgs {
             (Format::Rg32Sfloat, "pos"),
             // vec4 color;
             (Format::Rgba32Sfloat, "color"),
+            // vec2 aa;
+            (Format::Rg32Sfloat, "aa"),
         ))
     }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
-pub struct PushConstant {
-	inner: Vector4<f32>,
+/// Per draw arguments to pass into shader, as an instance rate vertex buffer.
+/// The transform takes a mesh's vertices to homogenous coords, the tint 
+/// multiplies the colour of each vertex, and the remaining fields describe 
+/// the mesh's gradient or texture paint, if any.
+/// layout(location = 3) in mat4 transform;
+/// layout(location = 7) in vec4 tint;
+/// layout(location = 8) in vec4 paint;
+/// layout(location = 9) in vec4 geometry;
+/// layout(location = 10) in vec4 stop_offsets;
+/// layout(location = 11) in mat4 stop_colours;
+/// layout(location = 15) in vec4 options;
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsStd140)]
+#[repr(C, align(4))]
+pub struct InstanceArgs {
+    /// mat4 transform;
+    pub transform: mat4,
+    /// vec4 tint;
+    pub tint: vec4,
+    /// vec4 paint; kind of paint (0 solid, 1 linear, 2 radial, 3 texture), number of 
+    /// gradient stops, texture mode, and alpha mode of the blend mode (0 straight, 
+    /// 1 premultiplied by the shader, 2 already premultiplied)
+    pub paint: vec4,
+    /// vec4 geometry; start and end of a linear gradient, center and radius of a radial one,
+    /// or origin and inverse size of a texture
+    pub geometry: vec4,
+    /// vec4 stop_offsets;
+    pub stop_offsets: vec4,
+    /// mat4 stop_colours; one colour per column
+    pub stop_colours: mat4,
+    /// vec4 options; if anti-aliasing is enabled (1 or 0)
+    pub options: vec4,
 }
 
-impl PushConstant {
-	pub fn new(scale_x: f32, scale_y: f32, trans_x: f32, trans_y: f32) -> Self {
-		Self {
-			inner: Vector4::new(scale_x, scale_y, trans_x, trans_y),
-		}
-	}
-
-	pub fn raw(&self) -> &[f32] { &self.inner.data }
+/// Required to send data into the shader.
+/// These names must match the shader.
+impl AsVertex for InstanceArgs {
+    fn vertex() -> VertexFormat {
+        VertexFormat::new((
+            // mat4 transform;
+            (Format::Rgba32Sfloat, "transform"),
+            (Format::Rgba32Sfloat, "transform"),
+            (Format::Rgba32Sfloat, "transform"),
+            (Format::Rgba32Sfloat, "transform"),
+            // vec4 tint;
+            (Format::Rgba32Sfloat, "tint"),
+            // vec4 paint;
+            (Format::Rgba32Sfloat, "paint"),
+            // vec4 geometry;
+            (Format::Rgba32Sfloat, "geometry"),
+            // vec4 stop_offsets;
+            (Format::Rgba32Sfloat, "stop_offsets"),
+            // mat4 stop_colours;
+            (Format::Rgba32Sfloat, "stop_colours"),
+            (Format::Rgba32Sfloat, "stop_colours"),
+            (Format::Rgba32Sfloat, "stop_colours"),
+            (Format::Rgba32Sfloat, "stop_colours"),
+            // vec4 options;
+            (Format::Rgba32Sfloat, "options"),
+        ))
+    }
+}
 
-	pub fn scale(&self) -> Vector2<f32> { Vector2::new(self.inner.x, self.inner.y) }
+impl InstanceArgs {
+    /// Arguments for drawing a mesh with transform and tint, painted with its vertex colours
+    pub fn new(transform: Matrix4<f32>, tint: Vector4<f32>) -> Self {
+        let transform: [[f32; 4]; 4] = transform.into();
+        let tint: [f32; 4] = tint.into();
+        Self {
+            transform: transform.into(),
+            tint: tint.into(),
+            paint: [0.0; 4].into(),
+            geometry: [0.0; 4].into(),
+            stop_offsets: [0.0; 4].into(),
+            stop_colours: [[1.0; 4]; 4].into(),
+            options: [1.0, 0.0, 0.0, 0.0].into(),
+        }
+    }
 
-	pub fn translation(&self) -> Vector2<f32> { Vector2::new(self.inner.z, self.inner.w) }
+    /// Set whether anti-aliased strokes and fringes are feathered, or drawn aliased
+    pub fn with_anti_alias(mut self, anti_alias: bool) -> Self {
+        self.options = [if anti_alias { 1.0 } else { 0.0 }, 0.0, 0.0, 0.0].into();
+        self
+    }
 
-	pub fn set_scale(&mut self, scale: Vector2<f32>) {
-		self.inner.x = scale.x;
-		self.inner.y = scale.y;
-	}
+    /// Paint the mesh with gradient
+    pub fn with_gradient(mut self, gradient: &Gradient) -> Self {
+        let (paint, geometry, stop_offsets, stop_colours) = gradient.shader_args();
+        self.paint = paint.into();
+        self.geometry = geometry.into();
+        self.stop_offsets = stop_offsets.into();
+        self.stop_colours = stop_colours.into();
+        self
+    }
 
-	pub fn set_translation(&mut self, translation: Vector2<f32>) {
-		self.inner.z = translation.x;
-		self.inner.w = translation.y;
+    /// Paint the mesh with a texture, whose bottom-left corner is at origin and that covers size
+    pub fn with_texture(mut self, mode: TextureMode, origin: [f32; 2], size: [f32; 2]) -> Self {
+        let mode = match mode {
+            TextureMode::Repeat => 0.0,
+            TextureMode::Clamp | TextureMode::Stretch => 1.0,
+        };
+        let inverse = |s: f32| if s != 0.0 { 1.0 / s } else { 0.0 };
+        self.paint = [3.0, 0.0, mode, 0.0].into();
+        self.geometry = [origin[0], origin[1], inverse(size[0]), inverse(size[1])].into();
+        self
     }
-}
 
-impl Default for PushConstant {
-	fn default() -> Self {
-		Self {
-			inner: Vector4::new(1.0, 1.0, 0.0, 0.0),
-		}
-	}
+    /// Set the alpha mode expected by blend, after the mesh's paint has been set
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        let alpha = match blend {
+            BlendMode::Alpha => 0.0,
+            BlendMode::Additive | BlendMode::Multiply | BlendMode::Screen => 1.0,
+            BlendMode::PremultipliedAlpha => 2.0,
+        };
+        let paint: &mut [f32; 4] = self.paint.as_mut();
+        paint[3] = alpha;
+        self
+    }
 }
 
+/// Index type of mesh triangles. By default indices are u16, limiting a single mesh
+/// to 65,535 vertices, enable the `u32-indices` feature for larger meshes.
+#[cfg(not(feature = "u32-indices"))]
 pub type IndexType = u16;
 
+/// Index type of mesh triangles. 
+#[cfg(feature = "u32-indices")]
+pub type IndexType = u32;
+
+/// Anti-aliasing mode of a vertex that is not anti-aliased
+pub const AA_NONE: f32 = 0.0;
+
+/// Anti-aliasing mode of a stroke vertex, whose edges are feathered
+pub const AA_STROKE: f32 = 1.0;
+
+/// Anti-aliasing mode of a vertex of the fringe stroked around a fill, which fades 
+/// out from the edge of the fill
+pub const AA_FRINGE: f32 = 2.0;
+
+/// Added to the anti-aliasing mode of vertices that the mesh's gradient or texture does 
+/// not paint, e.g. of the stroke of a path with a gradient fill, which keeps its own colour
+pub const AA_UNPAINTED: f32 = 4.0;
+
 /// Vertex information
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct VertexType {
     /// 2D position of vertex
     pub position: [f32; 2],
     /// Colour of vertex
     pub colour: [f32; 4],
+    /// Anti-aliasing coordinate of vertex, its side across a stroke (-1 left, 1 right) 
+    /// and its anti-aliasing mode, e.g. AA_STROKE, plus AA_UNPAINTED if the mesh's paint is not 
+    /// applied to it. The default is not anti-aliased.
+    #[serde(default)]
+    pub aa: [f32; 2],
 }
 
-/// Component for the triangles to be drawn to the screen
-#[derive(Debug)]
+/// Component for the triangles to be drawn to the screen.
+/// 
+/// Meshes are stored in a flagged storage, so that the renderer only re-uploads 
+/// geometry when a mesh has been modified. Any mutable access to a mesh flags it 
+/// as modified, so avoid mutably joining over meshes that have not changed.
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Mesh {
     /// list of vertices contained within mesh
     pub vertices: Vec<VertexType>,
@@ -105,6 +233,10 @@ pub struct Mesh {
     pub indices: Vec<IndexType>, 
     /// scaling factor to be applied to all vertices
     pub scale: Vector2<f32>,
+    /// gradient painting the mesh, if any, multiplied with the colour of each vertex, 
+    /// except those marked AA_UNPAINTED
+    #[serde(default)]
+    pub gradient: Option<Gradient>,
 }
 
 impl Default for Mesh {
@@ -113,25 +245,66 @@ impl Default for Mesh {
             vertices: Vec::new(),
             indices: Vec::new(),
             scale: Vector2::new(1.0, 1.0),
+            gradient: None,
         }
     }
 }
 
 impl Component for Mesh {
-    type Storage = DenseVecStorage<Self>;
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+/// Returns an vector of vertices expected by GLSL vert shader
+pub(crate) fn vertex_args(vertices: &[VertexType]) -> Vec<CustomArgs> {
+    vertices
+        .iter()
+        .map(|vertex| CustomArgs {
+            pos: vertex.position.into(),
+            color: vertex.colour.into(),
+            aa: vertex.aa.into(),
+        })
+        .collect()
+}
+
+/// Returns the bottom-left and top-right corners of the bounds of vertices, or None 
+/// if there are no vertices
+pub(crate) fn vertex_bounds(vertices: &[VertexType]) -> Option<(Vector2<f32>, Vector2<f32>)> {
+    let first = vertices.first()?.position;
+    Some(vertices.iter().fold(
+        (Vector2::new(first[0], first[1]), Vector2::new(first[0], first[1])),
+        |(min, max), vertex| {
+            let position = Vector2::new(vertex.position[0], vertex.position[1]);
+            (min.zip_map(&position, f32::min), max.zip_map(&position, f32::max))
+        },
+    ))
 }
 
 impl Mesh {
     /// Returns an vector of vertices expected by GLSL vert shader
     pub fn get_args(&self) -> Vec<CustomArgs> {
-        let mut vec = Vec::new();
-        vec.extend((0..self.vertices.len()).map(|i| {
-            //let vt: VertexType = self.vertices[i].clone();
-            CustomArgs {
-            pos: self.vertices[i].position.into(),
-            color: self.vertices[i].colour.into(),
-        }}));
-        vec
+        vertex_args(&self.vertices)
+    }
+
+    /// Returns the bottom-left and top-right corners of the mesh's bounds, or None 
+    /// if the mesh has no vertices
+    pub fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        vertex_bounds(&self.vertices)
+    }
+
+    /// Appends the triangles of other to this mesh, keeping this mesh's scale. If the 
+    /// indices of the combined mesh do not fit in IndexType, then this mesh is unchanged 
+    /// and TessellationError::TooManyVertices is returned.
+    pub fn append(&mut self, other: Mesh) -> Result<(), TessellationError> {
+        let offset = IndexType::try_from(self.vertices.len()).map_err(|_| TessellationError::TooManyVertices)?;
+        let indices = other
+            .indices
+            .into_iter()
+            .map(|index| index.checked_add(offset))
+            .collect::<Option<Vec<IndexType>>>()
+            .ok_or(TessellationError::TooManyVertices)?;
+        self.vertices.extend(other.vertices);
+        self.indices.extend(indices);
+        Ok(())
     }
 }
 
@@ -146,4 +319,378 @@ pub struct ActiveMesh {
 
 impl Component for ActiveMesh {
     type Storage = DenseVecStorage<Self>;
-}
\ No newline at end of file
+}
+
+/// Filter resource, used by the renderer to choose which Lyon meshes to render. 
+/// Useful for debug tooling, it can solo a set of meshes, mute a set of meshes, or 
+/// show only the meshes in a set of tagged groups. The default filter renders all 
+/// meshes. If an ActiveMesh is set, then the filter must also accept it for it 
+/// to be rendered.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct MeshFilter {
+    /// If not empty, only these meshes are rendered
+    pub include: HashSet<Entity>,
+    /// Meshes that are never rendered
+    pub exclude: HashSet<Entity>,
+    /// If not empty, only meshes with a MeshTag in this set are rendered
+    pub tags: HashSet<String>,
+}
+
+impl MeshFilter {
+    /// Filter that renders only the given meshes
+    pub fn solo<I: IntoIterator<Item = Entity>>(entities: I) -> Self {
+        Self {
+            include: entities.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Filter that renders all but the given meshes
+    pub fn mute<I: IntoIterator<Item = Entity>>(entities: I) -> Self {
+        Self {
+            exclude: entities.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Filter that renders only meshes tagged with one of the given tags
+    pub fn tagged<I: IntoIterator<Item = S>, S: Into<String>>(tags: I) -> Self {
+        Self {
+            tags: tags.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if the mesh of entity, with optional tag, should be rendered
+    pub fn accepts(&self, entity: Entity, tag: Option<&MeshTag>) -> bool {
+        (self.include.is_empty() || self.include.contains(&entity))
+            && !self.exclude.contains(&entity)
+            && (self.tags.is_empty() || tag.is_some_and(|tag| self.tags.contains(&tag.0)))
+    }
+}
+
+/// Tag component, placing a mesh in a named group that can be selected 
+/// with a MeshFilter.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct MeshTag(pub String);
+
+impl MeshTag {
+    pub fn new<S: Into<String>>(tag: S) -> Self {
+        Self(tag.into())
+    }
+}
+
+impl Component for MeshTag {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Component drawing the entity with the Mesh, or LyonMesh asset, of another entity, so 
+/// that many copies of a mesh share one upload of its geometry. The entity is otherwise drawn as if the mesh 
+/// were its own, with its own Transform, Tint, LyonLayer etc., while the mesh's scale and 
+/// gradient are shared. The source entity can be Hidden, so that only its instances are 
+/// drawn. If the entity has a Mesh or LyonMesh asset of its own, then that is drawn instead.
+///
+/// Consecutive instances of the same mesh, with the same texture, blend mode and clipping, 
+/// are drawn with a single draw call, so give instances their own LyonLayer to batch them all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstanceOf(pub Entity);
+
+impl Component for InstanceOf {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Component clipping the entity's Mesh to the mesh of another entity, the mask. 
+/// Only the parts of the mesh inside the mask's mesh, drawn with the mask's own 
+/// transform and scale, are drawn. The mask entity can be Hidden, so that only its 
+/// clipping effect is seen. Masks are not nested, i.e. a mask's own ClipMask is 
+/// ignored when it is used as a mask.
+///
+/// Clipping uses the stencil buffer, so must be enabled with RenderLyon::with_stencil.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipMask(pub Entity);
+
+impl Component for ClipMask {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Component clipping the entity's Mesh to a rectangle of the screen, e.g. for a 
+/// scroll region. The rectangle is in screen coordinates, set by the CoordinateSystem 
+/// resource, even for WorldSpace meshes. Scissor 
+/// rectangles are much cheaper than a ClipMask, and the two can be combined.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct ScissorRect {
+    /// Left edge of the rectangle
+    pub x: f32,
+    /// Edge of the rectangle nearest the origin, i.e. its bottom edge by default
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ScissorRect {
+    /// Create a rectangle with its corner nearest the origin at (x, y)
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+}
+
+impl Component for ScissorRect {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Component setting how the entity's Mesh is blended with what is drawn beneath it.
+/// Meshes without one are alpha blended. Meshes on the same LyonLayer are grouped by 
+/// blend mode, so that each mode's pipeline is bound once per layer, which means 
+/// that on a layer, meshes with different blend modes are not drawn in entity order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum BlendMode {
+    /// Vertex colours are blended by their alpha
+    #[default]
+    Alpha,
+    /// Vertex colours have already been multiplied by their alpha
+    PremultipliedAlpha,
+    /// Colours are added to those beneath, brightening them
+    Additive,
+    /// Colours are multiplied with those beneath, darkening them
+    Multiply,
+    /// Inverse colours are multiplied with those beneath, brightening them
+    Screen,
+}
+
+impl Component for BlendMode {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Space that a Lyon pass draws meshes without a WorldSpace marker in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CoordinateMode {
+    /// Meshes are drawn in screen space, as an overlay
+    #[default]
+    Screen,
+    /// Meshes are drawn through the active camera, as if marked with WorldSpace
+    World,
+    /// Meshes of entities with a UiTransform are placed by it, centred on the UI element, 
+    /// in the units of its width and height, and are ordered by its global z within their 
+    /// layer. Other meshes are drawn in screen space.
+    Ui,
+}
+
+/// Corner of the screen at the origin of screen space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// y increases up the screen
+    BottomLeft,
+    /// y increases down the screen, as for most SVG tools
+    TopLeft,
+}
+
+/// Units of screen space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Units {
+    /// Logical pixels, i.e. physical pixels divided by the hidpi factor
+    Logical,
+    /// Physical pixels of the framebuffer
+    Physical,
+    /// Fractions of the screen, from 0 to 1 across its width and height, so meshes 
+    /// are stretched with the window's aspect ratio
+    Normalised,
+}
+
+/// Resource setting the coordinate system of screen space, i.e. of meshes not drawn 
+/// through the camera, and of ScissorRects. Shared by all Lyon passes, and read each 
+/// frame, so it can be changed at runtime. The default is logical pixels from the 
+/// bottom-left corner of the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoordinateSystem {
+    pub origin: Origin,
+    pub units: Units,
+}
+
+impl Default for CoordinateSystem {
+    fn default() -> Self {
+        Self::new(Origin::BottomLeft, Units::Logical)
+    }
+}
+
+impl CoordinateSystem {
+    pub fn new(origin: Origin, units: Units) -> Self {
+        Self { origin, units }
+    }
+
+    /// Returns the physical pixels per unit, along x and y, of a screen of physical width 
+    /// and height, and hidpi factor
+    pub fn pixels_per_unit(&self, width: f32, height: f32, hidpi: f32) -> Vector2<f32> {
+        match self.units {
+            Units::Logical => Vector2::new(hidpi, hidpi),
+            Units::Physical => Vector2::new(1.0, 1.0),
+            Units::Normalised => Vector2::new(width, height),
+        }
+    }
+
+    /// Returns the projection from screen space to homogenous coords, of a screen of physical 
+    /// width and height, and hidpi factor. As in Vulkan, y is -1 at the top of the screen 
+    /// and 1 at the bottom. z is flattened, as Lyon meshes are 2D and must not be clipped 
+    /// by a transform's depth.
+    pub fn projection(&self, width: f32, height: f32, hidpi: f32) -> Matrix4<f32> {
+        let scale = self.pixels_per_unit(width, height, hidpi);
+        let (y, flip) = match self.origin {
+            Origin::BottomLeft => (1.0, -1.0),
+            Origin::TopLeft => (-1.0, 1.0),
+        };
+        Matrix4::new_translation(&Vector3::new(-1.0, y, 0.0))
+            * Matrix4::new_nonuniform_scaling(
+                &Vector3::new(scale.x * (2.0 / width), flip * scale.y * (2.0 / height), 0.0))
+    }
+}
+
+/// Marker component for meshes that live in the world, rather than on the screen. 
+/// World space meshes are drawn with the projection and view of the active camera, 
+/// so they move with the camera just like sprites. Meshes without this marker are 
+/// drawn in screen space, as an overlay.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct WorldSpace;
+
+impl Component for WorldSpace {
+    type Storage = NullStorage<Self>;
+}
+
+/// Draw order of a mesh. Meshes are drawn in painter's order, from the lowest layer 
+/// to the highest, so higher layers appear on top. Meshes without a layer are on 
+/// layer 0. Meshes on the same layer are grouped by BlendMode, and only meshes with 
+/// the same blend mode are drawn in entity order, so put meshes whose order matters 
+/// on separate layers if their blend modes differ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct LyonLayer(pub i32);
+
+impl Component for LyonLayer {
+    type Storage = DenseVecStorage<Self>;
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use amethyst::ecs::{Builder, World, WorldExt};
+
+    fn mesh(vertices: usize, indices: Vec<IndexType>) -> Mesh {
+        Mesh {
+            vertices: vec![VertexType::default(); vertices],
+            indices,
+            ..Mesh::default()
+        }
+    }
+
+    #[test]
+    fn append_offsets_indices() {
+        let mut a = mesh(3, vec![0, 1, 2]);
+        a.append(mesh(3, vec![2, 1, 0])).unwrap();
+        assert_eq!(a.vertices.len(), 6);
+        assert_eq!(a.indices, vec![0, 1, 2, 5, 4, 3]);
+    }
+
+    #[test]
+    fn append_rejects_index_overflow() {
+        let mut a = mesh(1, vec![0, 0, 0]);
+        let result = a.append(mesh(1, vec![IndexType::MAX, 0, 0]));
+        assert!(matches!(result, Err(TessellationError::TooManyVertices)));
+        // the mesh is left unchanged
+        assert_eq!(a.vertices.len(), 1);
+        assert_eq!(a.indices, vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn mesh_filter() {
+        let mut world = World::new();
+        let (a, b, c) = (world.create_entity().build(), world.create_entity().build(), world.create_entity().build());
+        let (ui, debug) = (MeshTag::new("ui"), MeshTag::new("debug"));
+
+        let all = MeshFilter::default();
+        assert!(all.accepts(a, None) && all.accepts(b, Some(&ui)));
+
+        let solo = MeshFilter::solo(vec![a, b]);
+        assert!(solo.accepts(a, None) && solo.accepts(b, Some(&ui)));
+        assert!(!solo.accepts(c, None));
+
+        let mute = MeshFilter::mute(vec![a]);
+        assert!(!mute.accepts(a, Some(&ui)));
+        assert!(mute.accepts(b, None) && mute.accepts(c, Some(&debug)));
+
+        let tagged = MeshFilter::tagged(vec!["ui"]);
+        assert!(tagged.accepts(a, Some(&ui)));
+        assert!(!tagged.accepts(b, Some(&debug)) && !tagged.accepts(c, None));
+
+        // every set must accept the mesh, and exclusion wins over inclusion
+        let combined = MeshFilter {
+            include: vec![a, b].into_iter().collect(),
+            exclude: vec![b].into_iter().collect(),
+            tags: vec!["ui".to_string()].into_iter().collect(),
+        };
+        assert!(combined.accepts(a, Some(&ui)));
+        assert!(!combined.accepts(a, Some(&debug)));
+        assert!(!combined.accepts(b, Some(&ui)));
+        assert!(!combined.accepts(c, Some(&ui)));
+    }
+
+    /// Screen of 800x600 physical pixels, i.e. 400x300 logical pixels
+    const WIDTH: f32 = 800.0;
+    const HEIGHT: f32 = 600.0;
+    const HIDPI: f32 = 2.0;
+
+    /// Homogenous coords of (x, y, z) in screen space, y is -1 at the top of the screen
+    fn project(coordinates: CoordinateSystem, x: f32, y: f32, z: f32) -> [f32; 3] {
+        let p = coordinates.projection(WIDTH, HEIGHT, HIDPI) * Vector4::new(x, y, z, 1.0);
+        [p.x / p.w, p.y / p.w, p.z / p.w]
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(
+            actual.iter().zip(&expected).all(|(a, e)| (a - e).abs() < 1e-5),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn projection_bottom_left() {
+        let coordinates = CoordinateSystem::new(Origin::BottomLeft, Units::Logical);
+        assert_close(project(coordinates, 0.0, 0.0, 0.0), [-1.0, 1.0, 0.0]);
+        assert_close(project(coordinates, 400.0, 300.0, 0.0), [1.0, -1.0, 0.0]);
+        assert_close(project(coordinates, 100.0, 225.0, 0.0), [-0.5, -0.5, 0.0]);
+    }
+
+    #[test]
+    fn projection_top_left() {
+        let coordinates = CoordinateSystem::new(Origin::TopLeft, Units::Logical);
+        assert_close(project(coordinates, 0.0, 0.0, 0.0), [-1.0, -1.0, 0.0]);
+        assert_close(project(coordinates, 400.0, 300.0, 0.0), [1.0, 1.0, 0.0]);
+        assert_close(project(coordinates, 100.0, 225.0, 0.0), [-0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn projection_units() {
+        let physical = CoordinateSystem::new(Origin::BottomLeft, Units::Physical);
+        assert_close(project(physical, 800.0, 600.0, 0.0), [1.0, -1.0, 0.0]);
+        assert_close(project(physical, 400.0, 300.0, 0.0), [0.0, 0.0, 0.0]);
+
+        let normalised = CoordinateSystem::new(Origin::BottomLeft, Units::Normalised);
+        assert_close(project(normalised, 1.0, 1.0, 0.0), [1.0, -1.0, 0.0]);
+        assert_close(project(normalised, 0.5, 0.25, 0.0), [0.0, 0.5, 0.0]);
+
+        let normalised = CoordinateSystem::new(Origin::TopLeft, Units::Normalised);
+        assert_close(project(normalised, 0.5, 0.25, 0.0), [0.0, -0.5, 0.0]);
+    }
+
+    #[test]
+    fn projection_flattens_z() {
+        let coordinates = CoordinateSystem::default();
+        assert_close(project(coordinates, 0.0, 0.0, 5.0), [-1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn pixels_per_unit() {
+        let ppu = |units| CoordinateSystem::new(Origin::BottomLeft, units).pixels_per_unit(WIDTH, HEIGHT, HIDPI);
+        assert_eq!(ppu(Units::Logical), Vector2::new(2.0, 2.0));
+        assert_eq!(ppu(Units::Physical), Vector2::new(1.0, 1.0));
+        assert_eq!(ppu(Units::Normalised), Vector2::new(800.0, 600.0));
+    }
+}
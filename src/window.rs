@@ -0,0 +1,155 @@
+//! Description:
+//!
+//! Render plugin for opening a window, and displaying a render target to it, whose
+//! depth attachment has a stencil buffer for utils::ClipMask.
+//!
+//! amethyst's RenderToWindow gives the target a D32Sfloat depth attachment, which
+//! has no stencil aspect, so clip masks can't be drawn with it. This plugin does the
+//! same, but with the first depth-stencil format the device supports.
+//!
+//! Copyright © 2020 Benedict Gaster. All rights reserved.
+//!
+use amethyst::{
+    config::{Config, ConfigError},
+    core::{
+        ecs::{DispatcherBuilder, ReadExpect, SystemData, World},
+        SystemBundle,
+    },
+    renderer::{
+        bundle::{ImageOptions, OutputColor, RenderPlan, RenderPlugin, Target, TargetPlanOutputs},
+        rendy::{
+            factory::Factory,
+            hal::{
+                adapter::PhysicalDevice,
+                command::{ClearColor, ClearDepthStencil, ClearValue},
+                format::{Format, ImageFeature},
+            },
+        },
+        types::Backend,
+        Kind,
+    },
+    window::{DisplayConfig, ScreenDimensions, Window, WindowBundle},
+};
+
+use amethyst_error::Error;
+
+use std::path::Path;
+
+/// Depth-stencil formats, in order of preference
+const DEPTH_STENCIL_FORMATS: [Format; 3] = [Format::D24UnormS8Uint, Format::D32SfloatS8Uint, Format::D16UnormS8Uint];
+
+/// Render plugin for opening a window and displaying a render target to it, as
+/// amethyst's RenderToWindow does, but with a stencil buffer that is cleared to 0 each
+/// frame, so that RenderLyon::with_stencil can draw clip masks:
+///
+/// ```ignore
+/// RenderingBundle::<DefaultBackend>::new()
+///     .with_plugin(RenderToWindowWithStencil::from_config_path(display_config_path)?.with_clear([0.0, 0.0, 0.0, 1.0]))
+///     .with_plugin(RenderLyon::default().with_stencil(true))
+/// ```
+#[derive(Default, Debug)]
+pub struct RenderToWindowWithStencil {
+    target: Target,
+    config: Option<DisplayConfig>,
+    dimensions: Option<ScreenDimensions>,
+    dirty: bool,
+    clear: Option<ClearColor>,
+}
+
+impl RenderToWindowWithStencil {
+    /// Create the plugin, opening a window with the config at path
+    pub fn from_config_path(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Ok(Self::from_config(DisplayConfig::load(path)?))
+    }
+
+    /// Create the plugin, opening a window with config
+    pub fn from_config(config: DisplayConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..Default::default()
+        }
+    }
+
+    /// Set the render target displayed in the window
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Clear the window with a linear RGBA colour each frame
+    pub fn with_clear(mut self, clear: impl Into<ClearColor>) -> Self {
+        self.clear = Some(clear.into());
+        self
+    }
+}
+
+/// First depth-stencil format that can be a depth-stencil attachment of the device
+fn depth_stencil_format<B: Backend>(factory: &Factory<B>) -> Result<Format, Error> {
+    DEPTH_STENCIL_FORMATS
+        .iter()
+        .copied()
+        .find(|&format| {
+            factory
+                .physical()
+                .format_properties(Some(format))
+                .optimal_tiling
+                .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .ok_or_else(|| Error::from_string("No depth-stencil attachment format is supported by the device"))
+}
+
+impl<B: Backend> RenderPlugin<B> for RenderToWindowWithStencil {
+    fn on_build<'a, 'b>(
+        &mut self,
+        world: &mut World,
+        builder: &mut DispatcherBuilder<'a, 'b>,
+    ) -> Result<(), Error> {
+        if let Some(config) = self.config.take() {
+            WindowBundle::from_config(config).build(world, builder)?;
+        }
+        Ok(())
+    }
+
+    /// The window's attachments are rebuilt when it is resized
+    fn should_rebuild(&mut self, world: &World) -> bool {
+        let dimensions = world.try_fetch::<ScreenDimensions>();
+        if self.dimensions.as_ref() != dimensions.as_deref() {
+            self.dirty = true;
+            self.dimensions = dimensions.map(|dimensions| (*dimensions).clone());
+            return false;
+        }
+        self.dirty
+    }
+
+    fn on_plan(
+        &mut self,
+        plan: &mut RenderPlan<B>,
+        factory: &mut Factory<B>,
+        world: &World,
+    ) -> Result<(), Error> {
+        self.dirty = false;
+
+        let window = <ReadExpect<'_, Window>>::fetch(world);
+        let surface = factory.create_surface(&window);
+        let dimensions = self
+            .dimensions
+            .as_ref()
+            .ok_or_else(|| Error::from_string("RenderToWindowWithStencil was planned before the window was created"))?;
+        let kind = Kind::D2(dimensions.width() as u32, dimensions.height() as u32, 1, 1);
+
+        plan.add_root(Target::Main);
+        plan.define_pass(
+            self.target,
+            TargetPlanOutputs {
+                colors: vec![OutputColor::Surface(surface, self.clear.map(ClearValue::Color))],
+                depth: Some(ImageOptions {
+                    kind,
+                    levels: 1,
+                    format: depth_stencil_format(factory)?,
+                    clear: Some(ClearValue::DepthStencil(ClearDepthStencil(0.0, 0))),
+                }),
+            },
+        )?;
+        Ok(())
+    }
+}
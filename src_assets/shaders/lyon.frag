@@ -1,8 +1,83 @@
 #version 450
 layout (location = 0) in vec4 inColor;
+layout (location = 1) in vec2 inPosition;
+layout (location = 2) flat in vec4 inPaint;
+layout (location = 3) flat in vec4 inGeometry;
+layout (location = 4) flat in vec4 inStopOffsets;
+layout (location = 5) flat in mat4 inStopColours;
+layout (location = 9) in vec2 inAA;
+
 layout (location = 0) out vec4 outColor;
 
+const float PAINT_LINEAR = 1.0;
+const float PAINT_RADIAL = 2.0;
+
+const float ALPHA_PREMULTIPLY = 1.0;
+const float ALPHA_PREMULTIPLIED = 2.0;
+
+const float AA_STROKE = 1.0;
+const float AA_FRINGE = 2.0;
+const float AA_UNPAINTED = 4.0;
+
+// coverage of the pixel, fading out over a pixel at the edges of anti-aliased strokes and fringes
+float coverage(float mode) {
+	float side = abs(inAA.x);
+	// triangles with every vertex on one side of a stroke have no width to fade across
+	float width = fwidth(inAA.x);
+	if (mode == AA_STROKE) {
+		return width > 0.0 ? clamp((1.0 - side) / width, 0.0, 1.0) : 1.0;
+	} else if (mode == AA_FRINGE) {
+		return width > 0.0 ? clamp(1.0 - side / width, 0.0, 1.0) : 0.0;
+	}
+	return 1.0;
+}
+
+// colour of the gradient at t, between the stops either side of it
+vec4 gradientColor(float t) {
+	int stops = int(inPaint.y);
+	if (stops == 0) {
+		return vec4(1.0);
+	}
+
+	vec4 color = inStopColours[0];
+	for (int i = 1; i < stops; i++) {
+		float start = inStopOffsets[i - 1];
+		float end = inStopOffsets[i];
+		color = mix(color, inStopColours[i], clamp((t - start) / max(end - start, 1e-5), 0.0, 1.0));
+	}
+	return color;
+}
+
 void main()
 {
-	outColor = inColor;
+	// vertices the paint does not apply to, e.g. the stroke of a gradient filled path, have 
+	// AA_UNPAINTED added to their anti-aliasing mode
+	float mode = round(inAA.y);
+	bool painted = mode < AA_UNPAINTED;
+	if (!painted) {
+		mode -= AA_UNPAINTED;
+	}
+
+	if (painted && inPaint.x == PAINT_LINEAR) {
+		vec2 start = inGeometry.xy;
+		vec2 line = inGeometry.zw - start;
+		float t = dot(inPosition - start, line) / max(dot(line, line), 1e-5);
+		outColor = inColor * gradientColor(clamp(t, 0.0, 1.0));
+	} else if (painted && inPaint.x == PAINT_RADIAL) {
+		float t = length(inPosition - inGeometry.xy) / max(inGeometry.z, 1e-5);
+		outColor = inColor * gradientColor(clamp(t, 0.0, 1.0));
+	} else {
+		outColor = inColor;
+	}
+	// blend modes other than alpha blending expect premultiplied colours, and if the colours 
+	// are already premultiplied, coverage must scale the whole colour
+	float alphaMode = round(inPaint.w);
+	if (alphaMode == ALPHA_PREMULTIPLIED) {
+		outColor *= coverage(mode);
+	} else {
+		outColor.a *= coverage(mode);
+		if (alphaMode == ALPHA_PREMULTIPLY) {
+			outColor.rgb *= outColor.a;
+		}
+	}
 }
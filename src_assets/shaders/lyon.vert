@@ -1,13 +1,24 @@
 #version 450
 layout (location = 0) in vec2 position;
 layout (location = 1) in vec4 color;
+layout (location = 2) in vec2 aa;
 
-layout (push_constant) uniform PushConstants {
-	vec2 scale;
-	vec2 translate;
-} pushConstants;
+// per draw arguments, from the instance buffer
+layout (location = 3) in mat4 transform;
+layout (location = 7) in vec4 tint;
+layout (location = 8) in vec4 paint;
+layout (location = 9) in vec4 geometry;
+layout (location = 10) in vec4 stop_offsets;
+layout (location = 11) in mat4 stop_colours;
+layout (location = 15) in vec4 options;
 
 layout (location = 0) out vec4 outColor;
+layout (location = 1) out vec2 outPosition;
+layout (location = 2) flat out vec4 outPaint;
+layout (location = 3) flat out vec4 outGeometry;
+layout (location = 4) flat out vec4 outStopOffsets;
+layout (location = 5) flat out mat4 outStopColours;
+layout (location = 9) out vec2 outAA;
 
 out gl_PerVertex {
 	vec4 gl_Position;
@@ -15,6 +26,14 @@ out gl_PerVertex {
 
 void main()
 {
-	outColor = color;
-	gl_Position = vec4((position * pushConstants.scale + pushConstants.translate), 0.0, 1.0);
-}
\ No newline at end of file
+	outColor = color * tint;
+	outPosition = position;
+	outPaint = paint;
+	outGeometry = geometry;
+	outStopOffsets = stop_offsets;
+	outStopColours = stop_colours;
+	// without anti-aliasing the coordinate is constant, so that the fragment shaders 
+	// fully cover strokes and drop fringes
+	outAA = options.x > 0.0 ? aa : vec2(0.0, aa.y);
+	gl_Position = transform * vec4(position, 0.0, 1.0);
+}
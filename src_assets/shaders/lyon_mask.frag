@@ -0,0 +1,15 @@
+#version 450
+layout (location = 9) in vec2 inAA;
+
+layout (location = 0) out vec4 outColor;
+
+const float AA_FRINGE = 2.0;
+
+// clip masks are only drawn into the stencil buffer, so the anti-aliasing fringe 
+// around fills, which would widen the mask, is discarded
+void main() {
+	if (round(inAA.y) == AA_FRINGE) {
+		discard;
+	}
+	outColor = vec4(0.0);
+}
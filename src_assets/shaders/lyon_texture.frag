@@ -0,0 +1,64 @@
+#version 450
+layout (set = 0, binding = 0) uniform sampler2D fill;
+
+layout (location = 0) in vec4 inColor;
+layout (location = 1) in vec2 inPosition;
+layout (location = 2) flat in vec4 inPaint;
+layout (location = 3) flat in vec4 inGeometry;
+layout (location = 9) in vec2 inAA;
+
+layout (location = 0) out vec4 outColor;
+
+const float MODE_REPEAT = 0.0;
+
+const float ALPHA_PREMULTIPLY = 1.0;
+const float ALPHA_PREMULTIPLIED = 2.0;
+
+const float AA_STROKE = 1.0;
+const float AA_FRINGE = 2.0;
+const float AA_UNPAINTED = 4.0;
+
+// coverage of the pixel, fading out over a pixel at the edges of anti-aliased strokes and fringes
+float coverage(float mode) {
+	float side = abs(inAA.x);
+	// triangles with every vertex on one side of a stroke have no width to fade across
+	float width = fwidth(inAA.x);
+	if (mode == AA_STROKE) {
+		return width > 0.0 ? clamp((1.0 - side) / width, 0.0, 1.0) : 1.0;
+	} else if (mode == AA_FRINGE) {
+		return width > 0.0 ? clamp(1.0 - side / width, 0.0, 1.0) : 0.0;
+	}
+	return 1.0;
+}
+
+void main()
+{
+	// vertices the paint does not apply to, e.g. the stroke of a gradient filled path, have 
+	// AA_UNPAINTED added to their anti-aliasing mode
+	float mode = round(inAA.y);
+	bool painted = mode < AA_UNPAINTED;
+	if (!painted) {
+		mode -= AA_UNPAINTED;
+	}
+
+	// texture coords from position, with v flipped as images are stored top row first
+	vec2 uv = (inPosition - inGeometry.xy) * inGeometry.zw;
+	uv.y = 1.0 - uv.y;
+
+	vec2 wrapped = inPaint.z == MODE_REPEAT ? fract(uv) : clamp(uv, 0.0, 1.0);
+
+	// gradients of the unwrapped coords avoid seams where tiles meet
+	vec4 texel = textureGrad(fill, wrapped, dFdx(uv), dFdy(uv));
+	outColor = painted ? inColor * texel : inColor;
+	// blend modes other than alpha blending expect premultiplied colours, and if the colours 
+	// are already premultiplied, coverage must scale the whole colour
+	float alphaMode = round(inPaint.w);
+	if (alphaMode == ALPHA_PREMULTIPLIED) {
+		outColor *= coverage(mode);
+	} else {
+		outColor.a *= coverage(mode);
+		if (alphaMode == ALPHA_PREMULTIPLY) {
+			outColor.rgb *= outColor.a;
+		}
+	}
+}